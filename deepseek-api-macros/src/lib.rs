@@ -0,0 +1,124 @@
+//! Derive macros for the `deepseek-api` crate.
+//!
+//! Enable them with the `derive` feature of `deepseek-api` and use them through
+//! `deepseek_api::request::Tool`.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, DeriveInput, Expr, ExprLit, Lit, LitStr, Meta};
+
+/// Derives `deepseek_api::request::Tool` for a struct describing the arguments of a tool.
+///
+/// The struct must also implement `schemars::JsonSchema` and `serde::Deserialize`. The name
+/// of the tool defaults to the struct name in snake case and its description to the doc
+/// comment of the struct; both can be set with `#[tool(name = "...", description = "...")]`.
+///
+/// # Examples
+///
+/// ```ignore
+/// /// Get the weather of a location.
+/// #[derive(Deserialize, JsonSchema, Tool)]
+/// #[tool(name = "get_weather")]
+/// struct GetWeather {
+///     /// The location to get the weather for.
+///     location: String,
+/// }
+/// ```
+#[proc_macro_derive(Tool, attributes(tool))]
+pub fn derive_tool(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_tool(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_tool(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut name = None;
+    let mut description = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("tool"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else if meta.path.is_ident("description") {
+                description = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `name` or `description`"))
+            }
+        })?;
+    }
+
+    let ident = &input.ident;
+    let name = name.unwrap_or_else(|| to_snake_case(&ident.to_string()));
+    let description = description.unwrap_or_else(|| doc_comment(&input.attrs));
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::deepseek_api::request::Tool for #ident #ty_generics #where_clause {
+            const NAME: &'static str = #name;
+            const DESCRIPTION: &'static str = #description;
+        }
+    })
+}
+
+/// Joins the lines of the doc comment of an item.
+fn doc_comment(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(meta) => match &meta.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(doc), ..
+                }) => Some(doc.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+fn to_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            // A word starts after a lowercase letter or a digit, and at the last capital of
+            // an acronym followed by a lowercase letter, as in `HTTPRequest`.
+            let starts_word = i > 0
+                && chars[i - 1] != '_'
+                && (chars[i - 1].is_lowercase()
+                    || chars[i - 1].is_numeric()
+                    || chars.get(i + 1).is_some_and(|next| next.is_lowercase()));
+            if starts_word {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_snake_case() {
+        assert_eq!(to_snake_case("GetWeather"), "get_weather");
+        assert_eq!(to_snake_case("HTTPRequest"), "http_request");
+        assert_eq!(to_snake_case("GetHTTPStatus"), "get_http_status");
+        assert_eq!(to_snake_case("ParseURL"), "parse_url");
+        assert_eq!(to_snake_case("Base64Decode"), "base64_decode");
+    }
+}
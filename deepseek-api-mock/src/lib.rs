@@ -0,0 +1,11 @@
+//! A local mock of the DeepSeek API, to test code using `deepseek-api` without network
+//! access or an API key.
+//!
+//! `MockServer` answers `/chat/completions`, `/beta/completions`, `/models` and
+//! `/user/balance` with scripted `MockResponse`s: JSON bodies, event streams, error
+//! statuses and dropped connections. It records every request so tests can check what
+//! the client actually sent.
+mod response;
+mod server;
+pub use response::*;
+pub use server::*;
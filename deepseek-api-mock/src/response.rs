@@ -0,0 +1,161 @@
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// A scripted reply of the `MockServer`.
+///
+/// # Examples
+///
+/// ```ignore
+/// // A chat completion answering "Hello".
+/// MockResponse::completion("Hello");
+/// // A rate limit error asking to retry in 1 second.
+/// MockResponse::error(429, "Rate limit reached").with_header("retry-after", "1");
+/// // Two chunks 50ms apart followed by `data: [DONE]`.
+/// MockResponse::sse(&[chunk1, chunk2]).with_chunk_delay(Duration::from_millis(50));
+/// // A connection closed before any response.
+/// MockResponse::disconnect();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockResponse {
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Body,
+    pub(crate) delay: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Body {
+    Full(String),
+    Chunks {
+        chunks: Vec<String>,
+        delay: Duration,
+        truncated: bool,
+    },
+    Disconnect,
+}
+
+impl MockResponse {
+    /// Returns a `200 OK` response with `body` as JSON.
+    pub fn json(body: &Value) -> Self {
+        Self::raw(200, "application/json", &body.to_string())
+    }
+
+    /// Returns a `200 OK` chat completion whose only choice answers `content`.
+    pub fn completion(content: &str) -> Self {
+        let body = json!({
+            "id": "mock",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "deepseek-chat",
+            "system_fingerprint": "mock",
+            "choices": [{
+                "index": 0,
+                "finish_reason": "stop",
+                "message": {"role": "assistant", "content": content}
+            }],
+            "usage": {
+                "completion_tokens": 1,
+                "prompt_tokens": 1,
+                "prompt_cache_hit_tokens": 0,
+                "prompt_cache_miss_tokens": 1,
+                "total_tokens": 2
+            }
+        });
+        Self::json(&body)
+    }
+
+    /// Returns a response with the status and the body as given.
+    pub fn raw(status: u16, content_type: &str, body: &str) -> Self {
+        MockResponse {
+            status,
+            headers: vec![("content-type".to_string(), content_type.to_string())],
+            body: Body::Full(body.to_string()),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Returns an error response in the envelope of the DeepSeek API,
+    /// `{"error": {"message": ..., "type": ..., "code": ...}}`.
+    pub fn error(status: u16, message: &str) -> Self {
+        let body = json!({
+            "error": {
+                "message": message,
+                "type": "mock_error",
+                "code": null,
+            }
+        });
+        Self::json(&body).with_status(status)
+    }
+
+    /// Returns a `200 OK` event stream sending each of `events` as a `data:` line, then
+    /// `data: [DONE]`.
+    pub fn sse(events: &[Value]) -> Self {
+        let mut chunks: Vec<String> = events
+            .iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .collect();
+        chunks.push("data: [DONE]\n\n".to_string());
+        Self::sse_chunks(chunks)
+    }
+
+    /// Returns a `200 OK` event stream writing `chunks` as they are, one write each.
+    ///
+    /// Use it to split events across writes or to send malformed events.
+    pub fn sse_chunks(chunks: Vec<String>) -> Self {
+        MockResponse {
+            status: 200,
+            headers: vec![("content-type".to_string(), "text/event-stream".to_string())],
+            body: Body::Chunks {
+                chunks,
+                delay: Duration::ZERO,
+                truncated: false,
+            },
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Returns a response that closes the connection without answering.
+    pub fn disconnect() -> Self {
+        MockResponse {
+            status: 0,
+            headers: Vec::new(),
+            body: Body::Disconnect,
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Sets the status code.
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Adds a header, e.g. `retry-after` or `x-request-id`.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Waits `delay` before sending anything.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Waits `delay` before each chunk of an event stream.
+    pub fn with_chunk_delay(mut self, delay: Duration) -> Self {
+        if let Body::Chunks { delay: d, .. } = &mut self.body {
+            *d = delay;
+        }
+        self
+    }
+
+    /// Closes the connection after the chunks of an event stream without ending the body,
+    /// as a server crashing mid-stream would.
+    pub fn truncated(mut self) -> Self {
+        if let Body::Chunks { truncated, .. } = &mut self.body {
+            *truncated = true;
+        }
+        self
+    }
+}
@@ -0,0 +1,343 @@
+use crate::response::{Body, MockResponse};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// The endpoints served by the `MockServer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Route {
+    /// `POST /chat/completions`
+    ChatCompletions,
+    /// `POST /beta/completions`, used by FIM and prefix completions.
+    BetaCompletions,
+    /// `GET /models`
+    Models,
+    /// `GET /user/balance`
+    UserBalance,
+}
+
+impl Route {
+    /// Returns the path of the endpoint.
+    pub fn path(&self) -> &'static str {
+        match self {
+            Route::ChatCompletions => "/chat/completions",
+            Route::BetaCompletions => "/beta/completions",
+            Route::Models => "/models",
+            Route::UserBalance => "/user/balance",
+        }
+    }
+
+    fn from_path(path: &str) -> Option<Route> {
+        [
+            Route::ChatCompletions,
+            Route::BetaCompletions,
+            Route::Models,
+            Route::UserBalance,
+        ]
+        .into_iter()
+        .find(|route| route.path() == path)
+    }
+}
+
+/// A request received by the `MockServer`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedRequest {
+    /// The method, e.g. `POST`.
+    pub method: String,
+    /// The path, without the query string.
+    pub path: String,
+    /// The headers, with lowercase names, in the order they were received.
+    pub headers: Vec<(String, String)>,
+    /// The raw body.
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    /// Returns the value of the first header named `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Decodes the body as JSON.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body is not valid JSON.
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).expect("the request body is not valid JSON")
+    }
+}
+
+#[derive(Default)]
+struct State {
+    queues: HashMap<Route, VecDeque<MockResponse>>,
+    defaults: HashMap<Route, MockResponse>,
+    requests: Vec<RecordedRequest>,
+}
+
+impl State {
+    fn next_response(&mut self, path: &str) -> MockResponse {
+        let Some(route) = Route::from_path(path) else {
+            return MockResponse::error(404, &format!("unknown path {}", path));
+        };
+        self.queues
+            .get_mut(&route)
+            .and_then(VecDeque::pop_front)
+            .or_else(|| self.defaults.get(&route).cloned())
+            .unwrap_or_else(|| {
+                MockResponse::error(404, &format!("no response scripted for {}", path))
+            })
+    }
+}
+
+/// A local HTTP server answering like the DeepSeek API with scripted responses.
+///
+/// The server listens on an ephemeral port of `127.0.0.1` and serves each connection on
+/// its own thread, so it works with both the async and the blocking client. Each request
+/// is recorded, then answered with the next response queued for its route by `enqueue`,
+/// or else with the one set by `set_default`, or else with a `404` error. Every response
+/// closes the connection.
+///
+/// The server stops when it is dropped.
+///
+/// # Examples
+///
+/// ```ignore
+/// let server = MockServer::start()?;
+/// server.enqueue(Route::ChatCompletions, MockResponse::json(&completion));
+///
+/// let client = DeepSeekClientBuilder::new("sk-test".to_string())
+///     .with_host(&server.url())
+///     .build()?;
+/// let resp = CompletionsRequestBuilder::new(&messages).do_request(&client).await?;
+///
+/// assert_eq!(server.requests()[0].json()["model"], "deepseek-chat");
+/// ```
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Starts a server on an ephemeral port.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no port can be bound.
+    pub fn start() -> io::Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let state = state.clone();
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let state = state.clone();
+                        thread::spawn(move || {
+                            let _ = serve(stream, &state);
+                        });
+                    }
+                }
+            })
+        };
+
+        Ok(MockServer {
+            addr,
+            state,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// Returns the URL of the server, to be passed to `DeepSeekClientBuilder::with_host`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Returns the address the server listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Queues `response` as the answer to the next request to `route` with no queued
+    /// response before it.
+    pub fn enqueue(&self, route: Route, response: MockResponse) -> &Self {
+        self.state()
+            .queues
+            .entry(route)
+            .or_default()
+            .push_back(response);
+        self
+    }
+
+    /// Answers the requests to `route` with `response` whenever its queue is empty.
+    pub fn set_default(&self, route: Route, response: MockResponse) -> &Self {
+        self.state().defaults.insert(route, response);
+        self
+    }
+
+    /// Returns the requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state().requests.clone()
+    }
+
+    /// Returns the requests received so far on `route`, oldest first.
+    pub fn requests_to(&self, route: Route) -> Vec<RecordedRequest> {
+        self.state()
+            .requests
+            .iter()
+            .filter(|request| request.path == route.path())
+            .cloned()
+            .collect()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wakes up the accept loop so that it sees the flag.
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn serve(mut stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let request = read_request(&mut stream)?;
+    let response = {
+        let mut state = state.lock().unwrap_or_else(|err| err.into_inner());
+        let response = state.next_response(&request.path);
+        state.requests.push(request);
+        response
+    };
+
+    thread::sleep(response.delay);
+    let (chunks, delay, truncated) = match response.body {
+        Body::Disconnect => return Ok(()),
+        Body::Full(body) => {
+            write_head(
+                &mut stream,
+                response.status,
+                &response.headers,
+                Some(body.len()),
+            )?;
+            stream.write_all(body.as_bytes())?;
+            return stream.flush();
+        }
+        Body::Chunks {
+            chunks,
+            delay,
+            truncated,
+        } => (chunks, delay, truncated),
+    };
+
+    write_head(&mut stream, response.status, &response.headers, None)?;
+    stream.flush()?;
+    for chunk in chunks {
+        thread::sleep(delay);
+        write!(stream, "{:x}\r\n{}\r\n", chunk.len(), chunk)?;
+        stream.flush()?;
+    }
+    if !truncated {
+        stream.write_all(b"0\r\n\r\n")?;
+    }
+    stream.flush()
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<RecordedRequest> {
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let len = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+
+    Ok(RecordedRequest {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+fn write_head(
+    stream: &mut TcpStream,
+    status: u16,
+    headers: &[(String, String)],
+    content_length: Option<usize>,
+) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", status, reason(status));
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    match content_length {
+        Some(len) => head.push_str(&format!("content-length: {}\r\n", len)),
+        None => head.push_str("transfer-encoding: chunked\r\n"),
+    }
+    head.push_str("connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        402 => "Payment Required",
+        404 => "Not Found",
+        422 => "Unprocessable Entity",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,248 @@
+use deepseek_api::request::MessageRequest;
+use deepseek_api::{
+    CompletionsRequestBuilder, DeepSeekClientBuilder, Error, FMICompletionsRequestBuilder,
+    RequestBuilder, RetryPolicy,
+};
+use deepseek_api_mock::{MockResponse, MockServer, Route};
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+fn chunk(content: &str, finish_reason: Value) -> Value {
+    json!({
+        "id": "c1",
+        "object": "chat.completion.chunk",
+        "created": 1,
+        "model": "deepseek-chat",
+        "system_fingerprint": "fp",
+        "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": finish_reason}]
+    })
+}
+
+fn client(server: &MockServer) -> deepseek_api::DeepSeekClient {
+    DeepSeekClientBuilder::new("sk-test".to_string())
+        .with_host(&server.url())
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_completion_is_recorded() {
+    let server = MockServer::start().unwrap();
+    server.enqueue(Route::ChatCompletions, MockResponse::completion("Hello"));
+
+    let messages = vec![MessageRequest::user("Hi")];
+    let resp = CompletionsRequestBuilder::new(&messages)
+        .temperature(0.5)
+        .unwrap()
+        .do_request(&client(&server))
+        .await
+        .unwrap()
+        .must_response();
+
+    assert_eq!(resp.choices[0].content(), "Hello");
+    let requests = server.requests_to(Route::ChatCompletions);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].header("Authorization"), Some("Bearer sk-test"));
+    let body = requests[0].json();
+    assert_eq!(body["messages"][0]["content"], "Hi");
+    assert_eq!(body["temperature"], 0.5);
+}
+
+#[tokio::test]
+async fn test_fim_uses_beta_endpoint() {
+    let server = MockServer::start().unwrap();
+    let fim = json!({
+        "id": "c1",
+        "object": "text_completion",
+        "created": 1,
+        "model": "deepseek-chat",
+        "system_fingerprint": "fp",
+        "choices": [{"index": 0, "finish_reason": "stop", "text": "    a + b\n"}],
+        "usage": {
+            "completion_tokens": 1,
+            "prompt_tokens": 1,
+            "prompt_cache_hit_tokens": 0,
+            "prompt_cache_miss_tokens": 1,
+            "total_tokens": 2
+        }
+    });
+    server.enqueue(Route::BetaCompletions, MockResponse::json(&fim));
+
+    let resp = FMICompletionsRequestBuilder::new("fn add(a: i32, b: i32) -> i32 {\n", "}")
+        .do_request(&client(&server))
+        .await
+        .unwrap()
+        .must_response();
+
+    assert_eq!(resp.choices[0].content(), "    a + b\n");
+    assert_eq!(server.requests()[0].path, "/beta/completions");
+}
+
+#[tokio::test]
+async fn test_stream_with_delays() {
+    let server = MockServer::start().unwrap();
+    server.enqueue(
+        Route::ChatCompletions,
+        MockResponse::sse(&[chunk("Hel", Value::Null), chunk("lo", json!("stop"))])
+            .with_chunk_delay(Duration::from_millis(50)),
+    );
+
+    let messages = vec![MessageRequest::user("Hi")];
+    let start = Instant::now();
+    let mut stream = CompletionsRequestBuilder::new(&messages)
+        .stream(true)
+        .do_request(&client(&server))
+        .await
+        .unwrap()
+        .must_stream();
+
+    let mut content = String::new();
+    while let Some(chunk) = stream.next().await {
+        content.push_str(
+            chunk.unwrap().choices[0]
+                .delta
+                .content
+                .as_deref()
+                .unwrap_or_default(),
+        );
+    }
+
+    assert_eq!(content, "Hello");
+    assert!(start.elapsed() >= Duration::from_millis(150));
+    assert_eq!(server.requests()[0].json()["stream"], true);
+}
+
+#[tokio::test]
+async fn test_truncated_stream_fails() {
+    let server = MockServer::start().unwrap();
+    server.enqueue(
+        Route::ChatCompletions,
+        MockResponse::sse_chunks(vec![format!("data: {}\n\n", chunk("Hel", Value::Null))])
+            .truncated(),
+    );
+
+    let messages = vec![MessageRequest::user("Hi")];
+    let mut stream = CompletionsRequestBuilder::new(&messages)
+        .stream(true)
+        .do_request(&client(&server))
+        .await
+        .unwrap()
+        .must_stream();
+
+    assert!(stream.next().await.unwrap().is_ok());
+    let mut failed = false;
+    while let Some(chunk) = stream.next().await {
+        failed |= chunk.is_err();
+    }
+    assert!(failed);
+}
+
+#[tokio::test]
+async fn test_errors_and_retries() {
+    let server = MockServer::start().unwrap();
+    server
+        .enqueue(
+            Route::ChatCompletions,
+            MockResponse::error(503, "Server overloaded").with_header("retry-after", "0"),
+        )
+        .enqueue(Route::ChatCompletions, MockResponse::completion("Hello"))
+        .enqueue(Route::ChatCompletions, MockResponse::disconnect())
+        .enqueue(
+            Route::ChatCompletions,
+            MockResponse::error(401, "Authentication Fails"),
+        );
+    let client = DeepSeekClientBuilder::new("sk-test".to_string())
+        .with_host(&server.url())
+        .with_retry_policy(RetryPolicy::default().with_max_attempts(3))
+        .build()
+        .unwrap();
+
+    let messages = vec![MessageRequest::user("Hi")];
+    let resp = CompletionsRequestBuilder::new(&messages)
+        .do_request(&client)
+        .await
+        .unwrap()
+        .must_response();
+    assert_eq!(resp.choices[0].content(), "Hello");
+    assert_eq!(server.requests().len(), 2);
+
+    // The request may have been processed, so it is not retried.
+    let err = CompletionsRequestBuilder::new(&messages)
+        .do_request(&client)
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, Error::Transport(_)), "{:?}", err);
+
+    let err = CompletionsRequestBuilder::new(&messages)
+        .do_request(&client)
+        .await
+        .err()
+        .unwrap();
+    match err {
+        Error::Api(err) => {
+            assert_eq!(err.status(), 401);
+            assert_eq!(err.message(), "Authentication Fails");
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[test]
+fn test_blocking_models_and_balance() {
+    let server = MockServer::start().unwrap();
+    server
+        .set_default(
+            Route::Models,
+            MockResponse::json(&json!({
+                "object": "list",
+                "data": [{"id": "deepseek-chat", "object": "model", "owned_by": "deepseek"}]
+            })),
+        )
+        .set_default(
+            Route::UserBalance,
+            MockResponse::json(&json!({
+                "is_available": true,
+                "balance_infos": [{
+                    "currency": "CNY",
+                    "total_balance": "10.00",
+                    "granted_balance": "0.00",
+                    "topped_up_balance": "10.00"
+                }]
+            })),
+        );
+    let client = DeepSeekClientBuilder::new("sk-test".to_string())
+        .with_host(&server.url())
+        .build_blocking()
+        .unwrap();
+
+    for _ in 0..2 {
+        assert_eq!(client.models().unwrap().data[0].id, "deepseek-chat");
+    }
+    assert_eq!(
+        client.balance().unwrap().balance_infos[0].total_balance,
+        "10.00"
+    );
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[0].method, "GET");
+    assert_eq!(server.requests_to(Route::UserBalance).len(), 1);
+}
+
+#[test]
+fn test_unscripted_route_is_not_found() {
+    let server = MockServer::start().unwrap();
+    let client = DeepSeekClientBuilder::new("sk-test".to_string())
+        .with_host(&server.url())
+        .build_blocking()
+        .unwrap();
+
+    match client.balance().unwrap_err() {
+        Error::Api(err) => assert_eq!(err.status(), 404),
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
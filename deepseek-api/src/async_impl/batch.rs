@@ -0,0 +1,319 @@
+use super::client::{ChatResponse, DeepSeekClient};
+use crate::batch::{retry_delay, BatchState, Checkpoint, LOOKAHEAD};
+use crate::{
+    response::{ChatCompletion, ChatCompletionStream, JSONChoiceStream, Usage},
+    BatchProgress, CompletionsRequestBuilder, PreparedRequest, Result, RetryPolicy,
+};
+use futures_util::future::BoxFuture;
+use futures_util::stream::{FuturesUnordered, Stream, StreamExt};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::Path,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+impl DeepSeekClient {
+    /// Sends many independent completion requests, at most `concurrency` at a time.
+    ///
+    /// The returned stream yields `(index, result)` for each request, in the order of
+    /// `requests`, where `index` is the position of the request in `requests`. Streaming
+    /// requests are collected into a single `ChatCompletion`. Each request goes through the
+    /// retries and the rate limiter of the client; a request that still fails with a transient
+    /// error, or whose response is cut short, is sent again according to the retry policy of
+    /// the batch.
+    ///
+    /// Requests are only built and sent as the stream is polled, so `requests` may be a lazy
+    /// iterator over a large input. The results received while an earlier request is still
+    /// pending are held until it completes, and no more than `4 * concurrency` requests are
+    /// started from it onwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests` - The requests to send.
+    /// * `concurrency` - The maximum number of requests in flight at once; `0` is treated as `1`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let conversations: Vec<Vec<MessageRequest>> = load_prompts()?;
+    /// let mut batch = client
+    ///     .complete_batch(conversations.iter().map(|m| CompletionsRequestBuilder::new(m)), 8)
+    ///     .with_checkpoint("prompts.checkpoint")?
+    ///     .on_progress(|progress| eprintln!("{} done", progress.completed));
+    ///
+    /// while let Some((index, result)) = batch.next().await {
+    ///     save(index, result?)?;
+    /// }
+    /// println!("{} tokens used", batch.usage().total_tokens);
+    /// ```
+    pub fn complete_batch<'a, I>(&'a self, requests: I, concurrency: usize) -> BatchStream<'a>
+    where
+        I: IntoIterator<Item = CompletionsRequestBuilder<'a>>,
+        I::IntoIter: Send + 'a,
+    {
+        BatchStream {
+            client: self,
+            requests: Box::new(requests.into_iter()),
+            next_index: 0,
+            concurrency: concurrency.max(1),
+            pending: VecDeque::new(),
+            done: HashMap::new(),
+            in_flight: FuturesUnordered::new(),
+            state: BatchState::new(),
+        }
+    }
+}
+
+/// The stream returned by `DeepSeekClient::complete_batch`.
+///
+/// Its settings must be changed before it is first polled.
+pub struct BatchStream<'a> {
+    client: &'a DeepSeekClient,
+    requests: Box<dyn Iterator<Item = CompletionsRequestBuilder<'a>> + Send + 'a>,
+    next_index: usize,
+    concurrency: usize,
+    /// The indices of the requests started and not yielded yet, in order.
+    pending: VecDeque<usize>,
+    /// The results received before the result of an earlier request.
+    done: HashMap<usize, Result<ChatCompletion>>,
+    in_flight: FuturesUnordered<BoxFuture<'a, (usize, Result<ChatCompletion>)>>,
+    state: BatchState<'a>,
+}
+
+impl<'a> BatchStream<'a> {
+    /// Sets how a request failing with a transient error is sent again, 3 attempts in total
+    /// by default.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.state.retry_policy = retry_policy;
+        self
+    }
+
+    /// Calls `on_progress` each time a result is yielded.
+    pub fn on_progress(mut self, on_progress: impl FnMut(&BatchProgress) + Send + 'a) -> Self {
+        self.state.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    /// Resumes the batch from the checkpoint at `path`, creating it if needed.
+    ///
+    /// The requests whose index is listed in the file are skipped, and the index of each
+    /// successful request is appended to it before its result is yielded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or written, or if a line is not an index.
+    pub fn with_checkpoint(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        self.state.checkpoint = Some(Checkpoint::open(path.as_ref())?);
+        Ok(self)
+    }
+
+    /// Returns the progress of the batch so far.
+    pub fn progress(&self) -> &BatchProgress {
+        &self.state.progress
+    }
+
+    /// Returns the usage of the successful requests so far, added up.
+    pub fn usage(&self) -> &Usage {
+        &self.state.progress.usage
+    }
+
+    /// Starts requests until `concurrency` of them are in flight, or `LOOKAHEAD` times as
+    /// many are waiting to be yielded.
+    fn fill(&mut self) {
+        let window = self.concurrency.saturating_mul(LOOKAHEAD);
+        while self.in_flight.len() < self.concurrency && self.pending.len() < window {
+            let Some(request) = self.requests.next() else {
+                break;
+            };
+            let index = self.next_index;
+            self.next_index += 1;
+            if self.state.skip(index) {
+                continue;
+            }
+            self.pending.push_back(index);
+            let client = self.client;
+            let retry_policy = self.state.retry_policy.clone();
+            self.in_flight.push(Box::pin(async move {
+                let result = match PreparedRequest::new(request) {
+                    Ok(prepared) => complete(client, prepared, &retry_policy).await,
+                    Err(err) => Err(err),
+                };
+                (index, result)
+            }));
+        }
+    }
+}
+
+impl Stream for BatchStream<'_> {
+    type Item = (usize, Result<ChatCompletion>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            this.fill();
+            let Some(&index) = this.pending.front() else {
+                return Poll::Ready(None);
+            };
+            if let Some(result) = this.done.remove(&index) {
+                this.pending.pop_front();
+                return Poll::Ready(Some((index, this.state.finish(index, result))));
+            }
+            match ready!(this.in_flight.poll_next_unpin(cx)) {
+                Some((index, result)) => {
+                    this.done.insert(index, result);
+                }
+                None => unreachable!("the requests not yielded yet are in flight"),
+            }
+        }
+    }
+}
+
+async fn complete(
+    client: &DeepSeekClient,
+    prepared: PreparedRequest,
+    retry_policy: &RetryPolicy,
+) -> Result<ChatCompletion> {
+    let mut attempt = 1;
+    loop {
+        let err = match send(client, prepared.clone()).await {
+            Ok(completion) => return Ok(completion),
+            Err(err) => err,
+        };
+        match retry_delay(retry_policy, attempt, &err) {
+            Some(delay) => tokio::time::sleep(delay).await,
+            None => return Err(err),
+        }
+        attempt += 1;
+    }
+}
+
+async fn send(client: &DeepSeekClient, prepared: PreparedRequest) -> Result<ChatCompletion> {
+    let resp: ChatResponse<ChatCompletion, ChatCompletionStream<JSONChoiceStream>> =
+        client.send_prepared(prepared).await?;
+    match resp {
+        ChatResponse::Full(completion) => Ok(completion),
+        ChatResponse::Stream(stream) => stream.collect_completion().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_impl::fake::{completion, fake_builder};
+    use crate::async_impl::transport::{ByteStream, Transport};
+    use crate::batch::LOOKAHEAD;
+    use crate::cassette::tests::temp_path;
+    use crate::request::MessageRequest;
+    use crate::{
+        CompletionsRequestBuilder, DeepSeekClientBuilder, Error, RetryPolicy, TransportError,
+    };
+    use futures_util::future::{self, BoxFuture};
+    use futures_util::StreamExt;
+    use http::Response;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_batch_retries_and_resumes() {
+        let path = temp_path("async-batch");
+        std::fs::write(&path, "1\n").unwrap();
+        let (builder, requests) = fake_builder(vec![
+            (503, "busy"),
+            (200, &completion("Hello")),
+            (200, &completion("Hello")),
+            (401, "bad key"),
+        ]);
+        let client = builder
+            .with_retry_policy(RetryPolicy::never())
+            .build()
+            .unwrap();
+
+        let messages = vec![MessageRequest::user("Hi")];
+        let mut calls = 0;
+        let mut batch = client
+            // One request at a time, so that the fake responses are received in order.
+            .complete_batch((0..4).map(|_| CompletionsRequestBuilder::new(&messages)), 1)
+            .with_retry_policy(RetryPolicy::default().with_base_delay(Duration::ZERO))
+            .with_checkpoint(&path)
+            .unwrap()
+            .on_progress(|_| calls += 1);
+
+        let mut results = Vec::new();
+        while let Some((index, result)) = batch.next().await {
+            results.push((index, result));
+        }
+        let progress = batch.progress().clone();
+        drop(batch);
+
+        let indices: Vec<_> = results.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![0, 2, 3]);
+        assert!(results[0].1.is_ok() && results[1].1.is_ok());
+        assert!(matches!(results[2].1, Err(Error::Api(_))));
+        assert_eq!(requests.lock().unwrap().len(), 4);
+        assert_eq!(calls, 3);
+        assert_eq!(
+            (progress.completed, progress.failed, progress.skipped),
+            (3, 1, 1)
+        );
+        assert_eq!(progress.usage.total_tokens, 4);
+
+        let mut done: Vec<_> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        done.sort();
+        assert_eq!(done, vec!["0", "1", "2"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// The message of the request a `StallingTransport` does not answer.
+    const STALL: &str = "Stall";
+
+    /// A `Transport` never answering the request sending `STALL`, and answering the others
+    /// at once.
+    struct StallingTransport {
+        sent: Arc<AtomicUsize>,
+    }
+
+    impl Transport for StallingTransport {
+        fn send(
+            &self,
+            request: http::Request<Vec<u8>>,
+        ) -> BoxFuture<'_, Result<Response<ByteStream>, TransportError>> {
+            self.sent.fetch_add(1, Ordering::SeqCst);
+            if String::from_utf8_lossy(request.body()).contains(STALL) {
+                return Box::pin(future::pending());
+            }
+            let chunk = Ok(bytes::Bytes::from(completion("Hello")));
+            let body: ByteStream = Box::pin(futures_util::stream::iter(vec![chunk]));
+            Box::pin(async move { Ok(Response::builder().status(200).body(body).unwrap()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_stops_behind_a_stalled_request() {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let client = DeepSeekClientBuilder::new("sk-test".to_string())
+            .with_host("http://fake")
+            .with_transport(StallingTransport {
+                sent: Arc::clone(&sent),
+            })
+            .build()
+            .unwrap();
+
+        let stalled = vec![MessageRequest::user(STALL)];
+        let messages = vec![MessageRequest::user("Hi")];
+        let requests = (0..100).map(|i| match i {
+            0 => CompletionsRequestBuilder::new(&stalled),
+            _ => CompletionsRequestBuilder::new(&messages),
+        });
+        let mut batch = client.complete_batch(requests, 2);
+        let next = tokio::time::timeout(Duration::from_millis(50), batch.next()).await;
+
+        assert!(next.is_err());
+        assert_eq!(sent.load(Ordering::SeqCst), 2 * LOOKAHEAD);
+    }
+}
@@ -0,0 +1,146 @@
+use super::transport::{ByteStream, Transport};
+use crate::cassette::{Cassette, Mode, Recording};
+use crate::TransportError;
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, Stream, StreamExt};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// A `Transport` recording the exchanges of `inner` to a cassette, or replaying them.
+pub(crate) struct CassetteTransport {
+    cassette: Arc<Cassette>,
+    inner: Arc<dyn Transport>,
+}
+
+impl CassetteTransport {
+    /// Opens the cassette at `path`; `inner` is only used in `Mode::Record`.
+    pub(crate) fn open(
+        path: &Path,
+        mode: Mode,
+        inner: Arc<dyn Transport>,
+    ) -> Result<Self, TransportError> {
+        Ok(CassetteTransport {
+            cassette: Arc::new(Cassette::open(path, mode)?),
+            inner,
+        })
+    }
+}
+
+impl Transport for CassetteTransport {
+    fn send(
+        &self,
+        request: http::Request<Vec<u8>>,
+    ) -> BoxFuture<'_, Result<http::Response<ByteStream>, TransportError>> {
+        Box::pin(async move {
+            if self.cassette.mode() == Mode::Replay {
+                let (parts, chunks) = self.cassette.replay(&request)?.into_parts();
+                let body: ByteStream = Box::pin(stream::iter(
+                    chunks.into_iter().map(|chunk| Ok(Bytes::from(chunk))),
+                ));
+                return Ok(http::Response::from_parts(parts, body));
+            }
+
+            let mut recording = self.cassette.record(&request);
+            let (parts, body) = self.inner.send(request).await?.into_parts();
+            recording.response(parts.status, &parts.headers);
+            let body: ByteStream = Box::pin(RecordingStream {
+                body,
+                recording: Some(recording),
+            });
+            Ok(http::Response::from_parts(parts, body))
+        })
+    }
+}
+
+/// A body passing its chunks through to the client while recording them.
+struct RecordingStream {
+    body: ByteStream,
+    recording: Option<Recording>,
+}
+
+impl Stream for RecordingStream {
+    type Item = Result<Bytes, TransportError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        match this.body.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                if let Some(recording) = &mut this.recording {
+                    recording.push(&chunk);
+                }
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(None) => match this.recording.take().map(Recording::finish) {
+                Some(Err(err)) => Poll::Ready(Some(Err(err))),
+                _ => Poll::Ready(None),
+            },
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_impl::fake::{completion, fake_builder};
+    use crate::cassette::{tests::temp_path, Mode};
+    use crate::request::MessageRequest;
+    use crate::{CompletionsRequestBuilder, DeepSeekClientBuilder, Error, RequestBuilder};
+    use futures_util::StreamExt;
+
+    const STREAM: &str = "data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"deepseek-chat\",\"system_fingerprint\":\"fp\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":\"stop\"}]}\n\ndata: [DONE]\n\n";
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let path = temp_path("async-cassette");
+        let messages = vec![MessageRequest::user("Hi")];
+
+        let (builder, requests) = fake_builder(vec![(200, &completion("Hello")), (200, STREAM)]);
+        let client = builder.with_cassette(&path, Mode::Record).build().unwrap();
+        let recorded = CompletionsRequestBuilder::new(&messages)
+            .do_request(&client)
+            .await
+            .unwrap()
+            .must_response();
+        let mut stream = CompletionsRequestBuilder::new(&messages)
+            .stream(true)
+            .do_request(&client)
+            .await
+            .unwrap()
+            .must_stream();
+        while stream.next().await.is_some() {}
+        drop(stream);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        let client = DeepSeekClientBuilder::new("sk-other".to_string())
+            .with_cassette(&path, Mode::Replay)
+            .build()
+            .unwrap();
+        let completion = CompletionsRequestBuilder::new(&messages)
+            .stream(true)
+            .do_request(&client)
+            .await
+            .unwrap()
+            .must_stream()
+            .collect_completion()
+            .await
+            .unwrap();
+        assert_eq!(completion.choices[0].content(), "Hi");
+        let replayed = CompletionsRequestBuilder::new(&messages)
+            .do_request(&client)
+            .await
+            .unwrap()
+            .must_response();
+        assert_eq!(replayed.choices[0].content(), recorded.choices[0].content());
+
+        let err = CompletionsRequestBuilder::new(&messages)
+            .do_request(&client)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Transport(_)));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+use super::client::DeepSeekClient;
+use super::json_stream::JsonStream;
+use crate::{
+    response::{
+        ChatCompletion, ChatCompletionStream, CompletionAccumulator, JSONChoiceStream, Usage,
+    },
+    Conversation, Error, RequestBuilder, Result, StreamError,
+};
+use futures_util::stream::{Stream, StreamExt};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+impl Conversation {
+    /// Sends the conversation and appends the reply of the model to it.
+    ///
+    /// # Returns
+    ///
+    /// The completion whose first choice was appended.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the request; the conversation is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// conversation.push(MessageRequest::user("Hello!"));
+    /// let completion = conversation.send(&client).await?;
+    /// ```
+    pub async fn send(&mut self, client: &DeepSeekClient) -> Result<ChatCompletion> {
+        let mut builder = self.request_builder().stream(false);
+        builder.stream_options = None;
+        let completion = builder.do_request(client).await?.must_response();
+        self.push_completion(&completion);
+        Ok(completion)
+    }
+
+    /// Sends the conversation as a streaming request.
+    ///
+    /// The returned stream yields the chunks as they arrive and appends the assembled reply
+    /// to the conversation once the server ends the stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the request; the conversation is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// conversation.push(MessageRequest::user("Hello!"));
+    /// let mut stream = conversation.send_stream(&client).await?;
+    /// while let Some(chunk) = stream.next().await {
+    ///     print!("{:?}", chunk?.choices[0].delta.content);
+    /// }
+    /// ```
+    pub async fn send_stream(&mut self, client: &DeepSeekClient) -> Result<ConversationStream<'_>> {
+        let stream = self
+            .request_builder()
+            .stream(true)
+            .do_request(client)
+            .await?
+            .must_stream();
+        Ok(ConversationStream {
+            conversation: self,
+            stream,
+            accumulator: CompletionAccumulator::new(),
+            completion: None,
+            failed: false,
+            done: false,
+        })
+    }
+}
+
+/// The stream returned by `Conversation::send_stream`.
+///
+/// It yields the chunks of the reply and, when the server ends the stream, appends the
+/// assembled message to the conversation. Nothing is appended if the stream yields an error
+/// or is dropped before its end.
+pub struct ConversationStream<'c> {
+    conversation: &'c mut Conversation,
+    stream: JsonStream<ChatCompletionStream<JSONChoiceStream>>,
+    accumulator: CompletionAccumulator,
+    completion: Option<ChatCompletion>,
+    failed: bool,
+    done: bool,
+}
+
+impl ConversationStream<'_> {
+    /// Returns the usage sent by the server, once the stream has been read up to it.
+    ///
+    /// It is only sent when the conversation sets `StreamOptions::new(true)`.
+    pub fn usage(&self) -> Option<&Usage> {
+        self.stream.usage()
+    }
+
+    /// Returns the assembled completion, once the stream has ended.
+    pub fn completion(&self) -> Option<&ChatCompletion> {
+        self.completion.as_ref()
+    }
+
+    /// Reads the rest of the stream and returns the assembled completion.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error yielded by the stream, or `Error::Stream` if that error was
+    /// already read from the stream.
+    pub async fn collect_completion(mut self) -> Result<ChatCompletion> {
+        while let Some(chunk) = self.next().await {
+            chunk?;
+        }
+        self.completion.ok_or_else(|| {
+            StreamError::Incomplete("the stream yielded an error before its end".to_string()).into()
+        })
+    }
+}
+
+impl Stream for ConversationStream<'_> {
+    type Item = Result<ChatCompletionStream<JSONChoiceStream>, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        match this.stream.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.accumulator.push(chunk.clone());
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(err))) => {
+                this.failed = true;
+                Poll::Ready(Some(Err(err)))
+            }
+            Poll::Ready(None) => {
+                this.done = true;
+                if this.failed {
+                    return Poll::Ready(None);
+                }
+                match std::mem::take(&mut this.accumulator).finish() {
+                    Ok(completion) => {
+                        this.conversation.push_completion(&completion);
+                        this.completion = Some(completion);
+                        Poll::Ready(None)
+                    }
+                    Err(err) => Poll::Ready(Some(Err(err.into()))),
+                }
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_impl::fake::{completion, fake_client};
+    use crate::request::{MessageRequest, StreamOptions};
+
+    fn chunk(content: &str, finish_reason: &str) -> String {
+        format!(
+            "data: {{\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"deepseek-chat\",\"system_fingerprint\":\"fp\",\"choices\":[{{\"index\":0,\"delta\":{{\"content\":\"{}\"}},\"finish_reason\":{}}}]}}\n\n",
+            content, finish_reason
+        )
+    }
+
+    #[tokio::test]
+    async fn test_send_appends_reply() {
+        let (client, requests) = fake_client(vec![(200, &completion("Hello"))]);
+        let mut conversation = Conversation::new().with_system("Be brief.");
+        conversation.push(MessageRequest::user("Hi"));
+
+        conversation.send(&client).await.unwrap();
+
+        assert_eq!(conversation.len(), 2);
+        assert_eq!(conversation.messages()[2].get_content(), "Hello");
+        let requests = requests.lock().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(requests[0].body()).unwrap();
+        assert_eq!(body["messages"].as_array().unwrap().len(), 2);
+        assert_eq!(body["stream"], false);
+    }
+
+    #[tokio::test]
+    async fn test_send_stream_appends_reply() {
+        let body = chunk("Hel", "null") + &chunk("lo", "\"stop\"") + "data: [DONE]\n\n";
+        let (client, _) = fake_client(vec![(200, &body)]);
+        let mut conversation = Conversation::new();
+        conversation.push(MessageRequest::user("Hi"));
+
+        let stream = conversation.send_stream(&client).await.unwrap();
+        let completion = stream.collect_completion().await.unwrap();
+
+        assert_eq!(
+            completion.choices[0].message.as_ref().unwrap().content,
+            "Hello"
+        );
+        assert_eq!(conversation.len(), 2);
+        assert_eq!(conversation.messages()[1].get_content(), "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_send_stream_incomplete() {
+        let body = chunk("Hel", "null");
+        let (client, _) = fake_client(vec![(200, &body)]);
+        let mut conversation = Conversation::new();
+        conversation.push(MessageRequest::user("Hi"));
+
+        let stream = conversation.send_stream(&client).await.unwrap();
+        let err = stream.collect_completion().await.unwrap_err();
+
+        assert!(matches!(err, Error::Stream(StreamError::Incomplete(_))));
+        assert_eq!(conversation.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_collect_after_reading_an_error() {
+        let body = chunk("Hel", "null") + "data: {not json\n\n";
+        let (client, _) = fake_client(vec![(200, &body)]);
+        let mut conversation = Conversation::new();
+        conversation.push(MessageRequest::user("Hi"));
+
+        let mut stream = conversation.send_stream(&client).await.unwrap();
+        assert!(stream.next().await.unwrap().is_ok());
+        assert!(stream.next().await.unwrap().is_err());
+        assert!(stream.next().await.is_none());
+        let err = stream.collect_completion().await.unwrap_err();
+
+        assert!(matches!(err, Error::Stream(StreamError::Incomplete(_))));
+        assert_eq!(conversation.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stream_options_include_usage() {
+        let body = chunk("Hello", "\"stop\"") + "data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"deepseek-chat\",\"system_fingerprint\":\"fp\",\"choices\":[],\"usage\":{\"completion_tokens\":1,\"prompt_tokens\":1,\"prompt_cache_hit_tokens\":0,\"prompt_cache_miss_tokens\":1,\"total_tokens\":2}}\n\n" + "data: [DONE]\n\n";
+        let (client, requests) = fake_client(vec![(200, &completion("Hello")), (200, &body)]);
+        let mut conversation = Conversation::new().stream_options(StreamOptions::new(true));
+        conversation.push(MessageRequest::user("Hi"));
+
+        conversation.send(&client).await.unwrap();
+        let mut stream = conversation.send_stream(&client).await.unwrap();
+        while stream.next().await.is_some() {}
+        assert_eq!(stream.usage().unwrap().total_tokens, 2);
+        drop(stream);
+
+        let requests = requests.lock().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(requests[0].body()).unwrap();
+        assert!(body.get("stream_options").is_none());
+        let body: serde_json::Value = serde_json::from_slice(requests[1].body()).unwrap();
+        assert_eq!(body["stream_options"]["include_usage"], true);
+    }
+}
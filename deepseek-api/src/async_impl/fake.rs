@@ -0,0 +1,85 @@
+use super::client::DeepSeekClient;
+use super::transport::{ByteStream, Transport};
+use crate::{DeepSeekClientBuilder, RetryPolicy, TransportError};
+use futures_util::future::BoxFuture;
+use http::Response;
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// The requests received by a `FakeTransport`.
+pub(crate) type Requests = Arc<Mutex<Vec<http::Request<Vec<u8>>>>>;
+
+/// A `Transport` answering with canned responses, in order.
+pub(crate) struct FakeTransport {
+    responses: Mutex<VecDeque<(u16, String)>>,
+    requests: Requests,
+}
+
+impl Transport for FakeTransport {
+    fn send(
+        &self,
+        request: http::Request<Vec<u8>>,
+    ) -> BoxFuture<'_, Result<Response<ByteStream>, TransportError>> {
+        self.requests.lock().unwrap().push(request);
+        let (status, text) = self.responses.lock().unwrap().pop_front().unwrap();
+        let chunk = Ok(bytes::Bytes::from(text));
+        let body: ByteStream = Box::pin(futures_util::stream::iter(vec![chunk]));
+        Box::pin(async move { Ok(Response::builder().status(status).body(body).unwrap()) })
+    }
+}
+
+/// Builds a client sending its requests to a `FakeTransport`, retrying without delay.
+pub(crate) fn fake_client(responses: Vec<(u16, &str)>) -> (DeepSeekClient, Requests) {
+    let (builder, requests) = fake_builder(responses);
+    (builder.build().unwrap(), requests)
+}
+
+/// Returns a builder for a client sending its requests to a `FakeTransport`, retrying
+/// without delay.
+pub(crate) fn fake_builder(responses: Vec<(u16, &str)>) -> (DeepSeekClientBuilder, Requests) {
+    let requests = Requests::default();
+    let transport = FakeTransport {
+        responses: Mutex::new(
+            responses
+                .into_iter()
+                .map(|(status, text)| (status, text.to_string()))
+                .collect(),
+        ),
+        requests: Arc::clone(&requests),
+    };
+    let builder = DeepSeekClientBuilder::new("sk-test".to_string())
+        .with_host("http://fake")
+        .with_retry_policy(RetryPolicy::default().with_base_delay(Duration::ZERO))
+        .with_transport(transport);
+    (builder, requests)
+}
+
+/// Returns the body of a chat completion whose only choice is `message`, finished with
+/// `finish_reason`.
+pub(crate) fn completion_with(finish_reason: &str, message: Value) -> String {
+    json!({
+        "id": "c1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "deepseek-chat",
+        "system_fingerprint": "fp",
+        "choices": [{"index": 0, "finish_reason": finish_reason, "message": message}],
+        "usage": {
+            "completion_tokens": 1,
+            "prompt_tokens": 1,
+            "prompt_cache_hit_tokens": 0,
+            "prompt_cache_miss_tokens": 1,
+            "total_tokens": 2
+        }
+    })
+    .to_string()
+}
+
+/// Returns the body of a chat completion answering `content`.
+pub(crate) fn completion(content: &str) -> String {
+    completion_with("stop", json!({"role": "assistant", "content": content}))
+}
@@ -1,19 +1,25 @@
-use anyhow::{Context, Error, Result};
-use futures_util::future;
-use futures_util::io::{AsyncBufReadExt, BufReader};
-use futures_util::stream::{Stream, StreamExt, TryStreamExt};
-use reqwest::Response;
+use super::transport::ByteStream;
+use crate::rate_limit::Permit;
+use crate::response::{
+    ChatCompletion, ChatCompletionStream, CompletionAccumulator, StreamChoice, Usage,
+};
+use crate::sse::{decode_event, event_usage, Event, EventParser};
+use crate::Error;
+use futures_util::stream::{Stream, StreamExt};
 use serde::de::DeserializeOwned;
 use std::{
+    collections::VecDeque,
+    marker::PhantomData,
     pin::Pin,
     task::{Context as TaskContext, Poll},
 };
 
 /// A stream that processes Server-Sent Events (SSE) and deserializes JSON data.
 ///
-/// The `JsonStream` struct wraps an asynchronous stream of lines from an HTTP response,
-/// where each line is expected to be a JSON object prefixed with "data: ". The stream
-/// terminates when it encounters a line with "data: [DONE]".
+/// The `JsonStream` struct parses an asynchronous HTTP response body as a `text/event-stream`
+/// and deserializes the data of each event as a JSON object. Comments, `event`, `id` and
+/// `retry` fields, multi-line `data` and any line ending are handled as the WHATWG
+/// specification describes. The stream terminates when it receives a `[DONE]` event.
 ///
 /// # Type Parameters
 ///
@@ -22,10 +28,9 @@ use std::{
 /// # Examples
 ///
 /// ```rust
-/// use reqwest::Response;
 /// use serde::Deserialize;
 /// use futures_util::stream::StreamExt;
-/// use deepseek_api::json_stream::JsonStream;
+/// use deepseek_api::r#async::{json_stream::JsonStream, ByteStream};
 ///
 /// #[derive(Debug, Deserialize)]
 /// struct MyData {
@@ -33,8 +38,8 @@ use std::{
 ///     value: u32,
 /// }
 ///
-/// async fn process_response(response: Response) {
-///     let mut stream = JsonStream::<MyData>::new(response);
+/// async fn process_response(body: ByteStream) {
+///     let mut stream = JsonStream::<MyData>::new(body);
 ///
 ///     while let Some(item) = stream.next().await {
 ///         match item {
@@ -47,69 +52,143 @@ use std::{
 ///
 /// # Errors
 ///
-/// The stream yields `anyhow::Error` if:
-/// - The line does not start with "data: "
-/// - The JSON deserialization fails
+/// The stream yields `Error::Stream` if the JSON deserialization fails, `Error::Api` if the
+/// server sends an error event or an `{"error": {...}}` payload, and `Error::Transport` if
+/// the response body cannot be read.
 ///
 /// # Methods
 ///
-/// * `new(response: Response) -> Self`: Creates a new `JsonStream` from an HTTP response.
+/// * `new(body: ByteStream) -> Self`: Creates a new `JsonStream` from an HTTP response body.
 ///
 /// # Trait Implementations
 ///
-/// * `Stream` for `JsonStream<T>`: Allows the `JsonStream` to be used as a stream of `Result<T, anyhow::Error>`.
+/// * `Stream` for `JsonStream<T>`: Allows the `JsonStream` to be used as a stream of `Result<T, deepseek_api::Error>`.
 pub struct JsonStream<T> {
-    inner: Pin<Box<dyn Stream<Item = Result<T, Error>> + Send>>,
+    _ph: PhantomData<fn() -> T>,
+    body: ByteStream,
+    parser: EventParser,
+    events: VecDeque<Event>,
+    usage: Option<Usage>,
+    permit: Option<Permit>,
+    done: bool,
 }
 
-impl<T: DeserializeOwned + Send + 'static> JsonStream<T> {
-    pub fn new(response: Response) -> Self {
-        let byte_stream = response
-            .bytes_stream()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
-
-        let async_read = byte_stream.into_async_read();
-        let processed = BufReader::new(async_read)
-            .lines()
-            .map_err(Error::from)
-            .take_while(|res| {
-                future::ready(match res {
-                    Ok(ref line) => line != "data: [DONE]",
-                    Err(_) => true,
-                })
-            })
-            .try_filter_map(|line| async move {
-                let line = line.trim();
-                if line.is_empty() || line == ": keep-alive" {
-                    return Ok(None);
-                }
-                let json = line
-                    .strip_prefix("data: ")
-                    .context("Missing 'data: ' prefix")?;
-                let obj = serde_json::from_str(json)?;
-                Ok(Some(obj))
-            });
-
+impl<T: DeserializeOwned> JsonStream<T> {
+    pub fn new(body: ByteStream) -> Self {
         JsonStream {
-            inner: Box::pin(processed),
+            _ph: PhantomData,
+            body,
+            parser: EventParser::default(),
+            events: VecDeque::new(),
+            usage: None,
+            permit: None,
+            done: false,
+        }
+    }
+
+    /// Holds the rate limiter permit of the request until the stream ends or is dropped,
+    /// and corrects its tokens with the usage sent by the server.
+    pub(crate) fn with_permit(mut self, permit: Option<Permit>) -> Self {
+        self.permit = permit;
+        self
+    }
+}
+
+impl<T> JsonStream<ChatCompletionStream<T>>
+where
+    T: StreamChoice + DeserializeOwned,
+{
+    /// Returns the usage sent by the server, once the stream has been read up to it.
+    ///
+    /// The usage is only sent when the request sets `StreamOptions { include_usage: true }`,
+    /// in a final chunk with no choices, so this returns `None` until that chunk is read.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// while let Some(chunk) = stream.next().await {
+    ///     println!("{:?}", chunk?);
+    /// }
+    /// println!("{:?}", stream.usage());
+    /// ```
+    pub fn usage(&self) -> Option<&Usage> {
+        self.usage.as_ref()
+    }
+
+    /// Consumes the stream and folds its chunks into a `ChatCompletion`.
+    ///
+    /// The result has the same shape as a non-streaming response; see
+    /// `CompletionAccumulator` for how the chunks are assembled.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error yielded by the stream, or `StreamError::Incomplete` if the
+    /// stream ended before every choice finished.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let completion = CompletionsRequestBuilder::new(&messages)
+    ///     .stream(true)
+    ///     .do_request(&client)
+    ///     .await?
+    ///     .must_stream()
+    ///     .collect_completion()
+    ///     .await?;
+    /// ```
+    pub async fn collect_completion(mut self) -> Result<ChatCompletion, Error> {
+        let mut accumulator = CompletionAccumulator::new();
+        while let Some(chunk) = self.next().await {
+            accumulator.push(chunk?);
         }
+        Ok(accumulator.finish()?)
     }
 }
 
-impl<T: Unpin> Stream for JsonStream<T> {
+impl<T: DeserializeOwned> Stream for JsonStream<T> {
     type Item = Result<T, Error>;
+
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
-        self.inner.as_mut().poll_next(cx)
+        let this = &mut *self;
+        loop {
+            if this.done {
+                this.permit = None;
+                return Poll::Ready(None);
+            }
+            if let Some(event) = this.events.pop_front() {
+                if let Some(usage) = event_usage(&event) {
+                    if let Some(permit) = &this.permit {
+                        permit.record_usage(&usage);
+                    }
+                    this.usage = Some(usage);
+                }
+                match decode_event(event) {
+                    Ok(Some(item)) => return Poll::Ready(Some(Ok(item))),
+                    Ok(None) => this.done = true,
+                    Err(err) => return Poll::Ready(Some(Err(err))),
+                }
+                continue;
+            }
+
+            match this.body.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(chunk))) => this.parser.feed(&chunk, &mut this.events),
+                Poll::Ready(Some(Err(err))) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{ApiError, StreamError};
     use bytes::Bytes;
     use futures_util::stream::StreamExt;
-    use http::StatusCode;
-    use reqwest::Response;
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -118,20 +197,15 @@ mod tests {
         value: u32,
     }
 
-    fn mock_response(data: Vec<Result<Bytes, reqwest::Error>>) -> Response {
-        let body = reqwest::Body::wrap_stream(futures_util::stream::iter(data));
-        let http_response = http::response::Response::builder()
-            .status(StatusCode::OK)
-            .body(body)
-            .unwrap();
-        Response::from(http_response)
+    fn mock_response(data: Vec<Result<Bytes, crate::TransportError>>) -> ByteStream {
+        Box::pin(futures_util::stream::iter(data))
     }
 
     #[tokio::test]
     async fn test_normal_sse_stream() {
         let data = vec![
-            Ok(Bytes::from("data: {\"id\":\"1\",\"value\":100}\n")),
-            Ok(Bytes::from("data: {\"id\":\"2\",\"value\":200}\n")),
+            Ok(Bytes::from("data: {\"id\":\"1\",\"value\":100}\n\n")),
+            Ok(Bytes::from("data: {\"id\":\"2\",\"value\":200}\n\n")),
         ];
         let response = mock_response(data);
         let mut stream = JsonStream::<TestData>::new(response);
@@ -160,7 +234,7 @@ mod tests {
     async fn test_chunked_data() {
         let data = vec![
             Ok(Bytes::from("data: {\"id\":\"3\",\"")),
-            Ok(Bytes::from("value\":300}\n")),
+            Ok(Bytes::from("value\":300}\n\n")),
         ];
         let response = mock_response(data);
         let mut stream = JsonStream::<TestData>::new(response);
@@ -179,9 +253,9 @@ mod tests {
     async fn test_empty_lines_and_done() {
         let data = vec![
             Ok(Bytes::from("\n")),
-            Ok(Bytes::from("data: {\"id\":\"4\",\"value\":400}\n")),
-            Ok(Bytes::from("data: [DONE]\n")),
-            Ok(Bytes::from("data: {\"id\":\"5\",\"value\":500}\n")),
+            Ok(Bytes::from("data: {\"id\":\"4\",\"value\":400}\n\n")),
+            Ok(Bytes::from("data: [DONE]\n\n")),
+            Ok(Bytes::from("data: {\"id\":\"5\",\"value\":500}\n\n")),
         ];
         let response = mock_response(data);
         let mut stream = JsonStream::<TestData>::new(response);
@@ -198,22 +272,108 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_invalid_prefix() {
-        let data = vec![Ok(Bytes::from("invalid data\n"))];
+    async fn test_ignored_fields() {
+        let data = vec![
+            Ok(Bytes::from(
+                "invalid data\r\n: comment\r\nevent: chunk\r\nid: 1\r\n",
+            )),
+            Ok(Bytes::from(
+                "data:{\"id\":\"6\",\r\ndata: \"value\":600}\r\n\r\n",
+            )),
+        ];
         let response = mock_response(data);
         let mut stream = JsonStream::<TestData>::new(response);
 
-        let err = stream.next().await.unwrap().unwrap_err();
-        assert!(err.to_string().contains("Missing 'data: ' prefix"));
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            TestData {
+                id: "6".into(),
+                value: 600
+            }
+        );
+        assert!(stream.next().await.is_none());
     }
 
     #[tokio::test]
     async fn test_malformed_json() {
-        let data = vec![Ok(Bytes::from("data: {invalid}\n"))];
+        let data = vec![Ok(Bytes::from("data: {invalid}\n\n"))];
+        let response = mock_response(data);
+        let mut stream = JsonStream::<TestData>::new(response);
+
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(err, Error::Stream(StreamError::Json { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_error_payload() {
+        let data = vec![
+            Ok(Bytes::from("data: {\"id\":\"7\",\"value\":700}\n\n")),
+            Ok(Bytes::from(
+                "data: {\"error\":{\"message\":\"rate limited\",\"code\":429}}\n\n",
+            )),
+        ];
         let response = mock_response(data);
         let mut stream = JsonStream::<TestData>::new(response);
 
+        assert!(stream.next().await.unwrap().is_ok());
         let err = stream.next().await.unwrap().unwrap_err();
-        assert!(err.is::<serde_json::Error>());
+        assert!(matches!(err, Error::Api(ApiError::RateLimitExceeded(_))));
+    }
+
+    #[tokio::test]
+    async fn test_collect_completion_with_tool_calls() {
+        let chunks = [
+            r#"{"id":"c2","object":"chat.completion.chunk","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[{"index":0,"delta":{"role":"assistant","content":"","tool_calls":[{"index":0,"id":"call_0","type":"function","function":{"name":"get_weather","arguments":""}}]},"finish_reason":null},{"index":1,"delta":{"role":"assistant","content":"Sunny"},"finish_reason":"stop"}]}"#,
+            r#"{"id":"c2","object":"chat.completion.chunk","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{}"}}]},"finish_reason":"tool_calls"}]}"#,
+        ];
+        let data = chunks
+            .iter()
+            .map(|chunk| Ok(Bytes::from(format!("data: {}\n\n", chunk))))
+            .collect();
+
+        let completion =
+            JsonStream::<ChatCompletionStream<crate::response::JSONChoiceStream>>::new(
+                mock_response(data),
+            )
+            .collect_completion()
+            .await
+            .unwrap();
+
+        assert_eq!(completion.choices.len(), 2);
+        let tool_calls = completion.choices[0]
+            .message
+            .as_ref()
+            .unwrap()
+            .tool_calls
+            .as_ref()
+            .unwrap();
+        assert_eq!(tool_calls[0].function.name, "get_weather");
+        assert_eq!(tool_calls[0].function.arguments, "{}");
+        assert_eq!(
+            completion.choices[1].message.as_ref().unwrap().content,
+            "Sunny"
+        );
+        assert_eq!(completion.usage.total_tokens, 0);
+    }
+
+    #[tokio::test]
+    async fn test_usage_after_stream() {
+        let chunks = [
+            r#"{"id":"t1","object":"text_completion","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[{"index":0,"text":"x","finish_reason":"stop"}]}"#,
+            r#"{"id":"t1","object":"text_completion","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[],"usage":{"completion_tokens":1,"prompt_tokens":2,"prompt_cache_hit_tokens":0,"prompt_cache_miss_tokens":2,"total_tokens":3}}"#,
+            "[DONE]",
+        ];
+        let data = chunks
+            .iter()
+            .map(|chunk| Ok(Bytes::from(format!("data: {}\n\n", chunk))))
+            .collect();
+
+        let mut stream = JsonStream::<ChatCompletionStream<crate::response::TextChoiceStream>>::new(
+            mock_response(data),
+        );
+        while let Some(chunk) = stream.next().await {
+            chunk.unwrap();
+        }
+        assert_eq!(stream.usage().unwrap().prompt_tokens, 2);
     }
 }
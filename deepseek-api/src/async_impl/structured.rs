@@ -0,0 +1,149 @@
+use super::client::DeepSeekClient;
+use crate::{
+    request::{MessageRequest, ResponseType},
+    structured::{json_messages, parse_reply, repair, DEFAULT_MAX_ATTEMPTS},
+    CompletionsRequestBuilder, RequestBuilder, Result,
+};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+
+impl DeepSeekClient {
+    /// Asks the model for a JSON reply and decodes it into `T`.
+    ///
+    /// The JSON Schema of `T` is appended to the system message `messages` start with, or
+    /// sent in a system message placed before them, and JSON mode is enabled. A reply that
+    /// cannot be decoded is sent back to the model with the decoding error, asking it to
+    /// correct it, up to 3 requests in total.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of a failed request, or `Error::StructuredOutput` holding the last
+    /// reply if none could be decoded.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// #[derive(Deserialize, JsonSchema)]
+    /// struct City {
+    ///     name: String,
+    ///     population: u64,
+    /// }
+    ///
+    /// let city: City = client
+    ///     .complete_json(&[MessageRequest::user("What is the largest city of France?")])
+    ///     .await?;
+    /// ```
+    pub async fn complete_json<T>(&self, messages: &[MessageRequest]) -> Result<T>
+    where
+        T: DeserializeOwned + JsonSchema,
+    {
+        self.complete_json_with(messages, DEFAULT_MAX_ATTEMPTS, |builder| builder)
+            .await
+    }
+
+    /// Like `complete_json`, with a custom number of attempts and request options.
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - The conversation to answer.
+    /// * `max_attempts` - The maximum number of requests sent. `0` is treated as `1`.
+    /// * `configure` - Sets the other options of each request, e.g. the model. JSON mode is
+    ///   always enabled and streaming disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of a failed request, or `Error::StructuredOutput` holding the last
+    /// reply if none could be decoded.
+    pub async fn complete_json_with<T, F>(
+        &self,
+        messages: &[MessageRequest],
+        max_attempts: usize,
+        configure: F,
+    ) -> Result<T>
+    where
+        T: DeserializeOwned + JsonSchema,
+        F: for<'b> Fn(CompletionsRequestBuilder<'b>) -> CompletionsRequestBuilder<'b>,
+    {
+        let mut messages = json_messages::<T>(messages);
+        let mut attempt = 1;
+        loop {
+            let builder = CompletionsRequestBuilder::new(&messages);
+            let completion = configure(builder)
+                .response_format(ResponseType::Json)
+                .stream(false)
+                .do_request(self)
+                .await?
+                .must_response();
+
+            match parse_reply(&completion) {
+                Ok(value) => return Ok(value),
+                Err((raw, source)) => {
+                    repair(&mut messages, attempt, max_attempts.max(1), raw, source)?
+                }
+            }
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_impl::fake::{completion, fake_client};
+    use crate::Error;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+    struct City {
+        name: String,
+        population: u64,
+    }
+
+    #[tokio::test]
+    async fn test_complete_json_repairs_reply() {
+        let invalid = completion(r#"{"name": "Paris"}"#);
+        let valid = completion(r#"{"name": "Paris", "population": 2100000}"#);
+        let (client, requests) = fake_client(vec![(200, &invalid), (200, &valid)]);
+
+        let city: City = client
+            .complete_json(&[MessageRequest::user("Largest city of France?")])
+            .await
+            .unwrap();
+
+        assert_eq!(city.name, "Paris");
+        let requests = requests.lock().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(requests[1].body()).unwrap();
+        assert_eq!(body["response_format"]["type"], "json_object");
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[2]["content"], r#"{"name": "Paris"}"#);
+        assert!(messages[3]["content"]
+            .as_str()
+            .unwrap()
+            .contains("missing field `population`"));
+    }
+
+    #[tokio::test]
+    async fn test_complete_json_gives_up() {
+        let invalid = completion("Paris");
+        let (client, requests) = fake_client(vec![(200, &invalid), (200, &invalid)]);
+
+        let err = client
+            .complete_json_with::<City, _>(
+                &[MessageRequest::user("Largest city of France?")],
+                2,
+                |builder| builder,
+            )
+            .await
+            .unwrap_err();
+
+        match err {
+            Error::StructuredOutput(err) => {
+                assert_eq!(err.attempts, 2);
+                assert_eq!(err.raw, "Paris");
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,312 @@
+use super::client::DeepSeekClient;
+use crate::{
+    request::{MessageRequest, Tool, ToolMessageRequest, ToolObject},
+    response::{ChatCompletion, FinishReason, ToolCall},
+    CompletionsRequestBuilder, Error, RequestBuilder, Result,
+};
+use futures_util::future::{self, BoxFuture, FutureExt};
+use std::{collections::HashMap, fmt::Display, future::Future};
+
+/// The default maximum number of requests sent by `run_with_tools`.
+const DEFAULT_MAX_ITERATIONS: usize = 10;
+
+type Handler = Box<dyn Fn(&ToolCall) -> BoxFuture<'static, Result<String, String>> + Send + Sync>;
+
+/// The tools the model may call, with the handlers running them.
+///
+/// Each tool is registered with the type of its arguments, which implements `Tool`; its
+/// handler receives the decoded arguments and returns the content of the tool message sent
+/// back to the model. Handlers may be synchronous (`register`) or asynchronous
+/// (`register_async`).
+///
+/// # Examples
+///
+/// ```ignore
+/// let registry = ToolRegistry::new()
+///     .register(|args: GetWeather| Ok::<_, String>(format!("24℃ in {}", args.location)))
+///     .register_async(|args: SearchWeb| async move { search(&args.query).await });
+///
+/// let mut messages = vec![MessageRequest::user("How's the weather in Hangzhou?")];
+/// let completion = client
+///     .run_with_tools(&mut messages, &registry, |builder| builder)
+///     .await?;
+/// ```
+pub struct ToolRegistry {
+    tools: Vec<ToolObject>,
+    handlers: HashMap<String, Handler>,
+    max_iterations: usize,
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        ToolRegistry {
+            tools: Vec::new(),
+            handlers: HashMap::new(),
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+        }
+    }
+}
+
+impl ToolRegistry {
+    /// Creates an empty `ToolRegistry` allowing 10 requests per `run_with_tools`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the tool `T` with a synchronous handler.
+    ///
+    /// A tool registered under the same name is replaced.
+    pub fn register<T, F, E>(self, handler: F) -> Self
+    where
+        T: Tool + 'static,
+        F: Fn(T) -> Result<String, E> + Send + Sync + 'static,
+        E: Display,
+    {
+        self.register_async(move |args: T| {
+            future::ready(handler(args).map_err(|err| err.to_string()))
+        })
+    }
+
+    /// Registers the tool `T` with an asynchronous handler.
+    ///
+    /// A tool registered under the same name is replaced.
+    pub fn register_async<T, F, Fut, E>(mut self, handler: F) -> Self
+    where
+        T: Tool + 'static,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, E>> + Send + 'static,
+        E: Display,
+    {
+        let handler: Handler = Box::new(move |call| match call.parse_arguments::<T>() {
+            Ok(args) => handler(args)
+                .map(|result| result.map_err(|err| err.to_string()))
+                .boxed(),
+            Err(err) => future::ready(Err(err.to_string())).boxed(),
+        });
+
+        self.tools.retain(|tool| tool.function.name != T::NAME);
+        self.tools.push(T::tool_object());
+        self.handlers.insert(T::NAME.to_string(), handler);
+        self
+    }
+
+    /// Sets the maximum number of requests sent by `run_with_tools`. `0` is treated as `1`.
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+
+    /// Returns the definitions of the registered tools, to be sent with a request.
+    pub fn tools(&self) -> &[ToolObject] {
+        &self.tools
+    }
+
+    /// Runs the handler of a tool call.
+    ///
+    /// # Returns
+    ///
+    /// The output of the handler, or a description of the failure if the tool is unknown,
+    /// its arguments are invalid or its handler failed.
+    pub async fn call(&self, call: &ToolCall) -> Result<String, String> {
+        match self.handlers.get(&call.function.name) {
+            Some(handler) => handler(call).await,
+            None => Err(format!("unknown tool `{}`", call.function.name)),
+        }
+    }
+
+    /// Runs all the tool calls of a turn concurrently, returning their tool messages in order.
+    async fn call_all(&self, calls: &[ToolCall]) -> Vec<MessageRequest> {
+        let results = future::join_all(calls.iter().map(|call| self.call(call))).await;
+        calls
+            .iter()
+            .zip(results)
+            .map(|(call, result)| tool_message(call, result))
+            .collect()
+    }
+}
+
+/// Builds the tool message reporting the result of a call to the model.
+fn tool_message(call: &ToolCall, result: Result<String, String>) -> MessageRequest {
+    let content = result.unwrap_or_else(|err| format!("Error: {}", err));
+    MessageRequest::Tool(ToolMessageRequest::new(&content, &call.id))
+}
+
+impl DeepSeekClient {
+    /// Sends the conversation and runs the tools called by the model until it stops.
+    ///
+    /// Each turn sends `messages` with the tools of `registry`. When the model answers with
+    /// tool calls, its message is appended to `messages`, the calls are run concurrently and
+    /// their results are appended as tool messages before the next turn. A failing tool is
+    /// reported to the model as a tool message starting with `Error:`. The loop ends with the
+    /// first answer that does not finish with `FinishReason::ToolCalls`, whose message is
+    /// also appended to `messages`.
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - The conversation, extended with the messages of every turn.
+    /// * `registry` - The tools the model may call.
+    /// * `configure` - Sets the other options of the request of each turn, e.g. the model.
+    ///   Streaming is always disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of a failed request, or `Error::TooManyToolIterations` if the model
+    /// still calls tools in the last of `registry`'s maximum number of requests. Those calls
+    /// are not run, and the message making them is not appended to `messages`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let completion = client
+    ///     .run_with_tools(&mut messages, &registry, |builder| {
+    ///         builder.use_model(ModelType::DeepSeekChat)
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn run_with_tools<F>(
+        &self,
+        messages: &mut Vec<MessageRequest>,
+        registry: &ToolRegistry,
+        configure: F,
+    ) -> Result<ChatCompletion>
+    where
+        F: for<'b> Fn(CompletionsRequestBuilder<'b>) -> CompletionsRequestBuilder<'b>,
+    {
+        for iteration in 1..=registry.max_iterations {
+            let builder = CompletionsRequestBuilder::new(messages).tools(registry.tools());
+            let completion = configure(builder)
+                .stream(false)
+                .do_request(self)
+                .await?
+                .must_response();
+
+            let Some(message) = completion
+                .choices
+                .first()
+                .and_then(|choice| choice.message.clone())
+            else {
+                return Ok(completion);
+            };
+            let tool_calls = message.tool_calls.clone().unwrap_or_default();
+            if completion.choices[0].finish_reason != FinishReason::ToolCalls
+                || tool_calls.is_empty()
+            {
+                messages.push(MessageRequest::Assistant(message));
+                return Ok(completion);
+            }
+            if iteration == registry.max_iterations {
+                break;
+            }
+
+            messages.push(MessageRequest::Assistant(message));
+            messages.extend(registry.call_all(&tool_calls).await);
+        }
+        Err(Error::TooManyToolIterations(registry.max_iterations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_impl::fake::{completion, completion_with, fake_client};
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize, JsonSchema)]
+    struct GetWeather {
+        location: String,
+    }
+
+    impl Tool for GetWeather {
+        const NAME: &'static str = "get_weather";
+        const DESCRIPTION: &'static str = "Get weather of a location";
+    }
+
+    #[derive(Deserialize, JsonSchema)]
+    struct GetTime {}
+
+    impl Tool for GetTime {
+        const NAME: &'static str = "get_time";
+        const DESCRIPTION: &'static str = "Get the current time";
+    }
+
+    fn tool_calls_completion() -> String {
+        completion_with(
+            "tool_calls",
+            json!({"role": "assistant", "content": "", "tool_calls": [
+                {"id": "call_0", "type": "function", "function": {"name": "get_weather", "arguments": "{\"location\":\"Paris\"}"}},
+                {"id": "call_1", "type": "function", "function": {"name": "get_time", "arguments": ""}},
+                {"id": "call_2", "type": "function", "function": {"name": "get_weather", "arguments": "{}"}},
+                {"id": "call_3", "type": "function", "function": {"name": "book_flight", "arguments": "{}"}}
+            ]}),
+        )
+    }
+
+    fn registry() -> ToolRegistry {
+        ToolRegistry::new()
+            .register(|args: GetWeather| Ok::<_, String>(format!("24℃ in {}", args.location)))
+            .register_async(|_: GetTime| async { Err::<String, _>("clock unavailable") })
+    }
+
+    #[tokio::test]
+    async fn test_run_with_tools() {
+        let tool_calls = tool_calls_completion();
+        let answer = completion("Sunny");
+        let (client, requests) = fake_client(vec![(200, &tool_calls), (200, &answer)]);
+
+        let mut messages = vec![MessageRequest::user("How's the weather in Paris?")];
+        let completion = client
+            .run_with_tools(&mut messages, &registry(), |builder| builder)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            completion.choices[0].message.as_ref().unwrap().content,
+            "Sunny"
+        );
+        assert_eq!(messages.len(), 7);
+        let contents: Vec<_> = messages[2..6]
+            .iter()
+            .map(|message| match message {
+                MessageRequest::Tool(tool) => (tool.tool_call_id.as_str(), tool.content.as_str()),
+                message => panic!("unexpected message: {:?}", message),
+            })
+            .collect();
+        assert_eq!(contents[0], ("call_0", "24℃ in Paris"));
+        assert_eq!(contents[1], ("call_1", "Error: clock unavailable"));
+        assert_eq!(contents[2].0, "call_2");
+        assert!(contents[2]
+            .1
+            .starts_with("Error: invalid arguments for `get_weather`"));
+        assert_eq!(contents[3], ("call_3", "Error: unknown tool `book_flight`"));
+
+        let requests = requests.lock().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(requests[1].body()).unwrap();
+        assert_eq!(body["tools"].as_array().unwrap().len(), 2);
+        assert_eq!(body["messages"].as_array().unwrap().len(), 6);
+        assert_eq!(body["stream"], false);
+    }
+
+    #[tokio::test]
+    async fn test_run_with_tools_max_iterations() {
+        let tool_calls = tool_calls_completion();
+        let (client, requests) = fake_client(vec![(200, &tool_calls), (200, &tool_calls)]);
+
+        let mut messages = vec![MessageRequest::user("How's the weather in Paris?")];
+        let err = client
+            .run_with_tools(
+                &mut messages,
+                &registry().with_max_iterations(2),
+                |builder| builder.stream(true),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, Error::TooManyToolIterations(2)));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        // Only the calls of the first answer were run.
+        assert_eq!(messages.len(), 6);
+        assert!(matches!(messages.last(), Some(MessageRequest::Tool(_))));
+    }
+}
@@ -0,0 +1,82 @@
+use crate::TransportError;
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use futures_util::stream::{Stream, TryStreamExt};
+use std::pin::Pin;
+
+/// The body of a response, delivered as a stream of byte chunks.
+pub type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, TransportError>> + Send>>;
+
+/// Sends HTTP requests on behalf of the async `DeepSeekClient`.
+///
+/// The client builds a complete `http::Request` (URL, headers and JSON body) and hands it to
+/// the transport, which returns the status, headers and a byte stream of the body. Implement
+/// this trait to run the client on another HTTP stack or against an in-memory fake.
+///
+/// # Examples
+///
+/// ```ignore
+/// struct MyTransport;
+///
+/// impl Transport for MyTransport {
+///     fn send(&self, request: http::Request<Vec<u8>>) -> BoxFuture<'_, Result<http::Response<ByteStream>, TransportError>> {
+///         Box::pin(async move { todo!() })
+///     }
+/// }
+///
+/// let client = DeepSeekClientBuilder::new("your_api_key".to_string())
+///     .with_transport(MyTransport)
+///     .build()?;
+/// ```
+pub trait Transport: Send + Sync + 'static {
+    /// Sends the request and returns the response once its headers have arrived.
+    fn send(
+        &self,
+        request: http::Request<Vec<u8>>,
+    ) -> BoxFuture<'_, Result<http::Response<ByteStream>, TransportError>>;
+}
+
+/// The default `Transport`, backed by a `reqwest::Client`.
+#[derive(Clone, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    /// Creates a new `ReqwestTransport` sending requests with `client`.
+    pub fn new(client: reqwest::Client) -> Self {
+        ReqwestTransport { client }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(
+        &self,
+        request: http::Request<Vec<u8>>,
+    ) -> BoxFuture<'_, Result<http::Response<ByteStream>, TransportError>> {
+        Box::pin(async move {
+            let request = reqwest::Request::try_from(request)?;
+            let resp = self.client.execute(request).await?;
+
+            let mut builder = http::Response::builder()
+                .status(resp.status())
+                .version(resp.version());
+            if let Some(headers) = builder.headers_mut() {
+                *headers = resp.headers().clone();
+            }
+            let body: ByteStream = Box::pin(resp.bytes_stream().map_err(TransportError::from));
+            Ok(builder
+                .body(body)
+                .expect("status and headers come from a valid response"))
+        })
+    }
+}
+
+/// Reads the whole body into memory.
+pub(crate) async fn read_body(body: ByteStream) -> Result<Vec<u8>, TransportError> {
+    body.try_fold(Vec::new(), |mut buf, chunk| async move {
+        buf.extend_from_slice(&chunk);
+        Ok(buf)
+    })
+    .await
+}
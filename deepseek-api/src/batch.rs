@@ -0,0 +1,176 @@
+use crate::{
+    response::{ChatCompletion, Usage},
+    Error, Result, RetryPolicy,
+};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// How many requests a batch starts at most past the oldest one not yielded yet, as a
+/// multiple of its concurrency. This bounds the results held while that request is pending.
+pub(crate) const LOOKAHEAD: usize = 4;
+
+/// How far a batch started with `complete_batch` has got.
+#[derive(Debug, Clone, Default)]
+pub struct BatchProgress {
+    /// The number of requests whose result has been yielded, successful or not.
+    pub completed: usize,
+    /// The number of those results that are errors.
+    pub failed: usize,
+    /// The number of requests skipped because the checkpoint lists them as done.
+    pub skipped: usize,
+    /// The usage of the successful requests, added up.
+    pub usage: Usage,
+}
+
+/// A file listing the indices of the requests of a batch already completed, one per line.
+pub(crate) struct Checkpoint {
+    done: HashSet<usize>,
+    file: File,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint at `path`, creating it if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or written, or if a line is not an index.
+    pub(crate) fn open(path: &Path) -> io::Result<Checkpoint> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        let done = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                line.parse().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid index in checkpoint {}: {}", path.display(), line),
+                    )
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Checkpoint { done, file })
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self.done.contains(&index)
+    }
+
+    fn record(&mut self, index: usize) -> io::Result<()> {
+        writeln!(self.file, "{}", index)?;
+        self.file.flush()?;
+        self.done.insert(index);
+        Ok(())
+    }
+}
+
+/// A function called with the progress of a batch each time it yields a result.
+pub(crate) type ProgressCallback<'a> = Box<dyn FnMut(&BatchProgress) + Send + 'a>;
+
+/// The bookkeeping shared by the async and the blocking batches.
+pub(crate) struct BatchState<'a> {
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) checkpoint: Option<Checkpoint>,
+    pub(crate) on_progress: Option<ProgressCallback<'a>>,
+    pub(crate) progress: BatchProgress,
+}
+
+impl BatchState<'_> {
+    pub(crate) fn new() -> Self {
+        BatchState {
+            retry_policy: RetryPolicy::default(),
+            checkpoint: None,
+            on_progress: None,
+            progress: BatchProgress::default(),
+        }
+    }
+
+    /// Returns `true`, and counts it as skipped, if the request at `index` is already done.
+    pub(crate) fn skip(&mut self, index: usize) -> bool {
+        let skip = self
+            .checkpoint
+            .as_ref()
+            .is_some_and(|checkpoint| checkpoint.contains(index));
+        if skip {
+            self.progress.skipped += 1;
+        }
+        skip
+    }
+
+    /// Records the result of the request at `index` and reports the progress.
+    ///
+    /// A successful result becomes an error if it cannot be written to the checkpoint, so
+    /// that the request is sent again when the batch is resumed.
+    pub(crate) fn finish(
+        &mut self,
+        index: usize,
+        result: Result<ChatCompletion>,
+    ) -> Result<ChatCompletion> {
+        let result = result.and_then(|completion| match &mut self.checkpoint {
+            Some(checkpoint) => checkpoint
+                .record(index)
+                .map(|_| completion)
+                .map_err(Error::from),
+            None => Ok(completion),
+        });
+        match &result {
+            Ok(completion) => self.progress.usage += &completion.usage,
+            Err(_) => self.progress.failed += 1,
+        }
+        self.progress.completed += 1;
+        if let Some(on_progress) = &mut self.on_progress {
+            on_progress(&self.progress);
+        }
+        result
+    }
+}
+
+/// Returns the delay before sending a failed request of a batch again, or `None` if it
+/// must not be retried.
+///
+/// Unlike the retries of the client, which stop once a response has been received, this
+/// also retries requests whose response could not be read to its end.
+pub(crate) fn retry_delay(policy: &RetryPolicy, attempt: u32, err: &Error) -> Option<Duration> {
+    let retry_after = match err {
+        Error::Api(err) if err.is_retryable() => err.retry_after(),
+        Error::Transport(_) | Error::Stream(_) => None,
+        _ => return None,
+    };
+    policy
+        .should_retry(attempt)
+        .then(|| policy.delay_for(attempt, retry_after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cassette::tests::temp_path;
+
+    #[test]
+    fn test_checkpoint_is_appended() {
+        let path = temp_path("checkpoint");
+        fs::write(&path, "3\n1\n").unwrap();
+
+        let mut checkpoint = Checkpoint::open(&path).unwrap();
+        assert!(checkpoint.contains(1) && checkpoint.contains(3));
+        assert!(!checkpoint.contains(0));
+        checkpoint.record(0).unwrap();
+        drop(checkpoint);
+
+        assert!(Checkpoint::open(&path).unwrap().contains(0));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "3\n1\n0\n");
+
+        fs::write(&path, "1\nnope\n").unwrap();
+        let err = Checkpoint::open(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+use crate::response::ModelType;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+/// What a model supports and how large its requests and replies may be.
+///
+/// The capabilities of the DeepSeek models are built in, and those of any model id can be
+/// registered or overridden at runtime with `ModelCapabilities::register`, e.g. for a
+/// fine-tune or a model served by an OpenAI-compatible server. Ids that were never
+/// registered get `ModelCapabilities::default()`.
+///
+/// # Examples
+///
+/// ```ignore
+/// ModelCapabilities::register(
+///     "my-reasoner",
+///     ModelCapabilities {
+///         is_reasoner: true,
+///         ..ModelCapabilities::default()
+///     },
+/// );
+/// assert!(ModelType::from("my-reasoner").capabilities().is_reasoner);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelCapabilities {
+    /// Maximum number of tokens of the prompt and the reply together.
+    pub context_length: u32,
+    /// Maximum number of tokens of the reply, `max_tokens` included.
+    pub max_output_tokens: u32,
+    /// Maximum number of tokens of the chain of thought, for reasoning models.
+    pub max_reasoning_tokens: Option<u32>,
+    /// Whether the model can serve FIM (fill in the middle) completions.
+    pub supports_fim: bool,
+    /// Whether the model accepts `tools`.
+    pub supports_tools: bool,
+    /// Whether the model reasons before answering, ignoring sampling parameters such as
+    /// `temperature` and returning `reasoning_content`.
+    pub is_reasoner: bool,
+}
+
+impl Default for ModelCapabilities {
+    /// Returns the capabilities assumed for an unregistered model: those of a chat model
+    /// with a 64K context, an 8K reply and tools, without FIM.
+    fn default() -> Self {
+        ModelCapabilities {
+            context_length: 64 * 1024,
+            max_output_tokens: 8 * 1024,
+            max_reasoning_tokens: None,
+            supports_fim: false,
+            supports_tools: true,
+            is_reasoner: false,
+        }
+    }
+}
+
+impl ModelCapabilities {
+    /// Returns the capabilities registered for the model `id`, if any.
+    pub fn lookup(id: &str) -> Option<ModelCapabilities> {
+        registry()
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(id)
+            .cloned()
+    }
+
+    /// Registers the capabilities of the model `id`, replacing the previous ones.
+    ///
+    /// The registry is shared by the whole process.
+    pub fn register(id: &str, capabilities: ModelCapabilities) {
+        registry()
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(id.to_string(), capabilities);
+    }
+}
+
+fn registry() -> &'static RwLock<HashMap<String, ModelCapabilities>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, ModelCapabilities>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let chat = ModelCapabilities {
+            supports_fim: true,
+            ..ModelCapabilities::default()
+        };
+        let reasoner = ModelCapabilities {
+            max_reasoning_tokens: Some(32 * 1024),
+            supports_tools: false,
+            is_reasoner: true,
+            ..ModelCapabilities::default()
+        };
+        RwLock::new(HashMap::from([
+            (ModelType::DeepSeekChat.id().to_string(), chat),
+            (ModelType::DeepSeekReasoner.id().to_string(), reasoner),
+        ]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_capabilities() {
+        assert!(ModelType::DeepSeekChat.capabilities().supports_fim);
+        assert!(ModelType::DeepSeekReasoner.capabilities().is_reasoner);
+        assert_eq!(
+            ModelType::from("never-registered").capabilities(),
+            ModelCapabilities::default()
+        );
+        assert!(ModelCapabilities::lookup("never-registered").is_none());
+    }
+
+    #[test]
+    fn test_register_overrides() {
+        let model = ModelType::from("test-fine-tune");
+        assert!(!model.is_reasoner());
+
+        ModelCapabilities::register(
+            "test-fine-tune",
+            ModelCapabilities {
+                context_length: 128 * 1024,
+                is_reasoner: true,
+                ..ModelCapabilities::default()
+            },
+        );
+        assert!(model.is_reasoner());
+        assert_eq!(model.get_limit_info(), (128, None, 8));
+    }
+}
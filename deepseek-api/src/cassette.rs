@@ -0,0 +1,375 @@
+//! Recording of the exchanges of a client with the API, to replay them offline.
+//!
+//! A cassette is a JSON file holding request/response pairs. In `Mode::Record` the client
+//! talks to the API as usual and every exchange is saved, streaming bodies included with
+//! their chunk boundaries. In `Mode::Replay` nothing is sent: each request is answered with
+//! the first unused recorded response whose request has the same method, path and body.
+//! Bodies are compared as JSON, so the order of their keys and their whitespace do not
+//! matter. The `Authorization` header is never written to the file.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let mode = if env::var("RECORD").is_ok() { Mode::Record } else { Mode::Replay };
+//! let client = DeepSeekClientBuilder::new(api_key)
+//!     .with_cassette("tests/cassettes/weather.json", mode)
+//!     .build()?;
+//! ```
+use crate::{TransportError, TransportErrorKind};
+use http::{HeaderMap, HeaderValue, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Whether a cassette records exchanges with the API or replays them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Sends requests to the API and saves each exchange, replacing the previous content of
+    /// the file.
+    Record,
+    /// Answers requests from the file without sending anything.
+    Replay,
+}
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    uri: String,
+    headers: Vec<(String, String)>,
+    body: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    chunks: Vec<Chunk>,
+}
+
+/// A chunk of a body, kept readable when it is valid UTF-8.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Chunk {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<&[u8]> for Chunk {
+    fn from(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Chunk::Text(text.to_string()),
+            Err(_) => Chunk::Bytes(bytes.to_vec()),
+        }
+    }
+}
+
+impl From<Chunk> for Vec<u8> {
+    fn from(chunk: Chunk) -> Self {
+        match chunk {
+            Chunk::Text(text) => text.into_bytes(),
+            Chunk::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+/// A cassette file opened by a client, shared by the transports of its clones.
+#[derive(Debug)]
+pub(crate) struct Cassette {
+    path: PathBuf,
+    mode: Mode,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    file: CassetteFile,
+    used: Vec<bool>,
+}
+
+impl Cassette {
+    /// Opens the cassette at `path`.
+    ///
+    /// # Errors
+    ///
+    /// In `Mode::Replay`, returns an error if the file cannot be read or decoded. In
+    /// `Mode::Record`, returns an error if an empty cassette cannot be written to it.
+    pub(crate) fn open(path: &Path, mode: Mode) -> Result<Cassette, TransportError> {
+        let file = match mode {
+            Mode::Record => CassetteFile::default(),
+            Mode::Replay => {
+                let content = fs::read(path).map_err(|err| cassette_error(path, err))?;
+                serde_json::from_slice(&content).map_err(|err| cassette_error(path, err))?
+            }
+        };
+        let cassette = Cassette {
+            path: path.to_path_buf(),
+            mode,
+            state: Mutex::new(State {
+                used: vec![false; file.interactions.len()],
+                file,
+            }),
+        };
+        if mode == Mode::Record {
+            cassette.save(&cassette.lock().file)?;
+        }
+        Ok(cassette)
+    }
+
+    pub(crate) fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Returns the recorded response to `request`, with one body chunk per recorded chunk.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no unused interaction matches the request.
+    pub(crate) fn replay(
+        &self,
+        request: &http::Request<Vec<u8>>,
+    ) -> Result<http::Response<Vec<Vec<u8>>>, TransportError> {
+        let method = request.method().as_str();
+        let uri = uri(request);
+        let body = normalize(request.body());
+
+        let mut state = self.lock();
+        let State { file, used } = &mut *state;
+        let index = file
+            .interactions
+            .iter()
+            .enumerate()
+            .position(|(i, interaction)| {
+                !used[i]
+                    && interaction.request.method == method
+                    && interaction.request.uri == uri
+                    && interaction.request.body == body
+            })
+            .ok_or_else(|| {
+                cassette_error(
+                    &self.path,
+                    format!("no recorded interaction left for {} {}", method, uri),
+                )
+            })?;
+        used[index] = true;
+
+        let response = file.interactions[index].response.clone();
+        let mut builder = http::Response::builder().status(
+            StatusCode::from_u16(response.status).map_err(|err| cassette_error(&self.path, err))?,
+        );
+        for (name, value) in &response.headers {
+            builder = builder.header(name, value);
+        }
+        builder
+            .body(response.chunks.into_iter().map(Vec::from).collect())
+            .map_err(|err| cassette_error(&self.path, err))
+    }
+
+    /// Starts recording the exchange of `request`.
+    pub(crate) fn record(self: &Arc<Self>, request: &http::Request<Vec<u8>>) -> Recording {
+        Recording {
+            cassette: Arc::clone(self),
+            request: RecordedRequest {
+                method: request.method().to_string(),
+                uri: uri(request),
+                headers: headers(request.headers()),
+                body: normalize(request.body()),
+            },
+            response: None,
+        }
+    }
+
+    fn push(&self, interaction: Interaction) -> Result<(), TransportError> {
+        let mut state = self.lock();
+        state.file.interactions.push(interaction);
+        state.used.push(true);
+        self.save(&state.file)
+    }
+
+    fn save(&self, file: &CassetteFile) -> Result<(), TransportError> {
+        let content =
+            serde_json::to_vec_pretty(file).map_err(|err| cassette_error(&self.path, err))?;
+        fs::write(&self.path, content).map_err(|err| cassette_error(&self.path, err))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// An exchange being recorded, saved to the cassette once the response body has been read.
+///
+/// A body that is dropped before its end is saved as far as it was read.
+pub(crate) struct Recording {
+    cassette: Arc<Cassette>,
+    request: RecordedRequest,
+    response: Option<RecordedResponse>,
+}
+
+impl Recording {
+    /// Sets the status and headers of the response.
+    pub(crate) fn response(&mut self, status: StatusCode, headers: &HeaderMap) {
+        self.response = Some(RecordedResponse {
+            status: status.as_u16(),
+            headers: self::headers(headers),
+            chunks: Vec::new(),
+        });
+    }
+
+    /// Appends a chunk of the response body.
+    pub(crate) fn push(&mut self, chunk: &[u8]) {
+        if let Some(response) = &mut self.response {
+            response.chunks.push(Chunk::from(chunk));
+        }
+    }
+
+    /// Saves the exchange to the cassette.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cassette cannot be written.
+    pub(crate) fn finish(mut self) -> Result<(), TransportError> {
+        self.save()
+    }
+
+    fn save(&mut self) -> Result<(), TransportError> {
+        match self.response.take() {
+            Some(response) => self.cassette.push(Interaction {
+                request: self.request.clone(),
+                response,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        let _ = self.save();
+    }
+}
+
+fn uri(request: &http::Request<Vec<u8>>) -> String {
+    request
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "/".to_string())
+}
+
+fn headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if name == http::header::AUTHORIZATION {
+                HeaderValue::from_static(REDACTED)
+            } else {
+                value.clone()
+            };
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Returns the body as JSON, or as a JSON string if it is not JSON.
+fn normalize(body: &[u8]) -> Value {
+    if body.is_empty() {
+        return Value::Null;
+    }
+    serde_json::from_slice(body)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned()))
+}
+
+fn cassette_error(
+    path: &Path,
+    err: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+) -> TransportError {
+    TransportError::new(
+        TransportErrorKind::Other,
+        format!("cassette {}: {}", path.display(), err.into()),
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Returns a path in the temporary directory that no other test uses.
+    pub(crate) fn temp_path(name: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        std::env::temp_dir().join(format!(
+            "deepseek-api-{}-{}-{}.json",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ))
+    }
+
+    fn request(body: &str) -> http::Request<Vec<u8>> {
+        http::Request::post("https://api.deepseek.com/chat/completions")
+            .header("Authorization", "Bearer sk-secret")
+            .body(body.as_bytes().to_vec())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let path = temp_path("cassette");
+        let cassette = Arc::new(Cassette::open(&path, Mode::Record).unwrap());
+        for (body, reply) in [(r#"{"a":1,"b":2}"#, "first"), (r#"{"a":1}"#, "second")] {
+            let mut recording = cassette.record(&request(body));
+            recording.response(StatusCode::OK, &HeaderMap::new());
+            recording.push(reply.as_bytes());
+            recording.push(&[0xff]);
+            recording.finish().unwrap();
+        }
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains(REDACTED));
+        assert!(!content.contains("sk-secret"));
+
+        let cassette = Cassette::open(&path, Mode::Replay).unwrap();
+        let resp = cassette.replay(&request(r#"{"a": 1}"#)).unwrap();
+        assert_eq!(resp.body(), &vec![b"second".to_vec(), vec![0xff]]);
+        let resp = cassette.replay(&request(r#"{ "b": 2, "a": 1 }"#)).unwrap();
+        assert_eq!(resp.body()[0], b"first");
+
+        let err = cassette.replay(&request(r#"{"a":1}"#)).unwrap_err();
+        assert!(err.to_string().contains("no recorded interaction left"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_dropped_body_is_recorded() {
+        let path = temp_path("cassette");
+        let cassette = Arc::new(Cassette::open(&path, Mode::Record).unwrap());
+        drop(cassette.record(&request("{}")));
+        let mut recording = cassette.record(&request("{}"));
+        recording.response(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new());
+        drop(recording);
+
+        let cassette = Cassette::open(&path, Mode::Replay).unwrap();
+        let resp = cassette.replay(&request("{}")).unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(cassette.replay(&request("{}")).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,20 +1,19 @@
-use anyhow::{Ok, Result};
 use std::env;
-cfg_if::cfg_if! {
-    if #[cfg(feature = "is_sync")] {
-        use reqwest::blocking::ClientBuilder as ReqwestClientBuilder;
-    } else {
-        use reqwest::ClientBuilder as ReqwestClientBuilder;
-    }
-}
-use crate::DeepSeekClient;
-use reqwest::header::HeaderMap;
+
+use crate::{Result, RetryPolicy, ValidationError};
+use http::HeaderMap;
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
+use std::path::{Path, PathBuf};
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
+use std::sync::Arc;
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
 use std::time::Duration;
 
 /// A builder for constructing a `DeepSeekClient` instance with customizable options.
 ///
-/// The `DeepSeekClientBuilder` allows you to configure the API key, timeout, and host
-/// for the `DeepSeekClient` before building it.
+/// The `DeepSeekClientBuilder` allows you to configure the API key, timeout, host, retry
+/// policy and HTTP transport for the `DeepSeekClient` before building it. `build` creates the async
+/// client and `build_blocking` creates the blocking one.
 ///
 /// # Examples
 ///
@@ -24,10 +23,21 @@ use std::time::Duration;
 ///     .build()
 ///     .expect("Failed to build client");
 /// ```
+#[derive(Clone)]
 pub struct DeepSeekClientBuilder {
     api_key: String,
     timeout: Option<u64>,
     host: String,
+    retry_policy: RetryPolicy,
+    default_headers: HeaderMap,
+    #[cfg(feature = "is_async")]
+    transport: Option<Arc<dyn crate::r#async::Transport>>,
+    #[cfg(feature = "is_sync")]
+    blocking_transport: Option<Arc<dyn crate::blocking::Transport>>,
+    #[cfg(any(feature = "is_async", feature = "is_sync"))]
+    cassette: Option<(PathBuf, crate::cassette::Mode)>,
+    #[cfg(any(feature = "is_async", feature = "is_sync"))]
+    rate_limiter: Option<Arc<crate::rate_limit::RateLimiter>>,
 }
 
 impl Default for DeepSeekClientBuilder {
@@ -37,6 +47,7 @@ impl Default for DeepSeekClientBuilder {
     /// `DEEPSEEK_API_KEY`. If the environment variable is not set, it uses a empty string as
     /// default API key. The `timeout` is set to `None`, indicating
     /// no default timeout, and the `host` is set to the default API URL `"https://api.deepseek.com"`.
+    /// Requests are not retried unless a `RetryPolicy` is configured.
     ///
     /// # Returns
     /// A new instance of `DeepSeekClientBuilder` with default values.
@@ -47,6 +58,16 @@ impl Default for DeepSeekClientBuilder {
             api_key,
             timeout: None,
             host: String::from("https://api.deepseek.com"),
+            retry_policy: RetryPolicy::never(),
+            default_headers: HeaderMap::new(),
+            #[cfg(feature = "is_async")]
+            transport: None,
+            #[cfg(feature = "is_sync")]
+            blocking_transport: None,
+            #[cfg(any(feature = "is_async", feature = "is_sync"))]
+            cassette: None,
+            #[cfg(any(feature = "is_async", feature = "is_sync"))]
+            rate_limiter: None,
         }
     }
 }
@@ -62,17 +83,31 @@ impl DeepSeekClientBuilder {
     ///
     /// A new instance of `DeepSeekClientBuilder` with default settings.
     ///
-    /// The default host is set to `"https://api.deepseek.com"`, and no timeout is configured.
+    /// The default host is set to `"https://api.deepseek.com"`, and no timeout or retry
+    /// policy is configured.
     pub fn new(api_key: String) -> Self {
         Self {
             api_key,
             timeout: None,
             host: "https://api.deepseek.com".to_string(),
+            retry_policy: RetryPolicy::never(),
+            default_headers: HeaderMap::new(),
+            #[cfg(feature = "is_async")]
+            transport: None,
+            #[cfg(feature = "is_sync")]
+            blocking_transport: None,
+            #[cfg(any(feature = "is_async", feature = "is_sync"))]
+            cassette: None,
+            #[cfg(any(feature = "is_async", feature = "is_sync"))]
+            rate_limiter: None,
         }
     }
 
     /// Sets the timeout duration for the client.
     ///
+    /// The timeout only applies to the default reqwest transport; a custom transport set with
+    /// `with_transport` or `with_blocking_transport` is responsible for its own timeouts.
+    ///
     /// # Arguments
     ///
     /// * `duration` - A `u64` value representing the timeout duration in seconds.
@@ -125,17 +160,146 @@ impl DeepSeekClientBuilder {
         self
     }
 
-    /// Builds the `Client` instance using the configured options.
+    /// Sets the retry policy for the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - A `RetryPolicy` describing how transient failures are retried.
+    ///
+    /// # Returns
+    ///
+    /// The `DeepSeekClientBuilder` instance with the retry policy configured.
+    /// ```ignore
+    /// let builder = DeepSeekClientBuilder::new("your_api_key".to_string())
+    ///     .with_retry_policy(RetryPolicy::default().with_max_attempts(5));
+    /// ```
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Sets headers sent with every request, e.g. for a proxy in front of the API.
+    ///
+    /// The `Authorization` header is always set from the API key. A request can add its own
+    /// headers on top of these with `extra_headers`.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - The headers to send with every request.
+    ///
+    /// # Returns
+    ///
+    /// The `DeepSeekClientBuilder` instance with the default headers configured.
+    /// ```ignore
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert("x-team", HeaderValue::from_static("search"));
+    /// let builder = DeepSeekClientBuilder::new("your_api_key".to_string())
+    ///     .with_default_headers(headers);
+    /// ```
+    pub fn with_default_headers(mut self, headers: HeaderMap) -> Self {
+        self.default_headers = headers;
+        self
+    }
+
+    /// Sets the transport used by the async client instead of the default reqwest one.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - A `Transport` sending the HTTP requests built by the client.
+    ///
+    /// # Returns
+    ///
+    /// The `DeepSeekClientBuilder` instance with the transport configured.
+    /// ```ignore
+    /// let builder = DeepSeekClientBuilder::new("your_api_key".to_string())
+    ///     .with_transport(ReqwestTransport::new(reqwest::Client::new()));
+    /// ```
+    #[cfg(feature = "is_async")]
+    pub fn with_transport(mut self, transport: impl crate::r#async::Transport) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Sets the transport used by the blocking client instead of the default reqwest one.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - A blocking `Transport` sending the HTTP requests built by the client.
     ///
     /// # Returns
     ///
-    /// A `Result` containing the constructed `Client` on success, or an error
+    /// The `DeepSeekClientBuilder` instance with the transport configured.
+    /// ```ignore
+    /// let builder = DeepSeekClientBuilder::new("your_api_key".to_string())
+    ///     .with_blocking_transport(ReqwestTransport::new(reqwest::blocking::Client::new()));
+    /// ```
+    #[cfg(feature = "is_sync")]
+    pub fn with_blocking_transport(mut self, transport: impl crate::blocking::Transport) -> Self {
+        self.blocking_transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Limits the requests sent by the clients built from this builder and their clones.
+    ///
+    /// The limits are enforced on the client side and shared by every client built from
+    /// this builder (or a clone of it) after this call. See `RateLimit` for how requests
+    /// and tokens are counted.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The requests per minute, tokens per minute and requests in flight allowed.
+    ///
+    /// # Returns
+    ///
+    /// The `DeepSeekClientBuilder` instance with the rate limit configured.
+    /// ```ignore
+    /// let builder = DeepSeekClientBuilder::new("your_api_key".to_string())
+    ///     .with_rate_limit(RateLimit::default().with_requests_per_minute(60));
+    /// ```
+    #[cfg(any(feature = "is_async", feature = "is_sync"))]
+    pub fn with_rate_limit(mut self, limit: crate::RateLimit) -> Self {
+        self.rate_limiter = Some(Arc::new(crate::rate_limit::RateLimiter::new(limit)));
+        self
+    }
+
+    /// Records the exchanges of the client with the API to a cassette file, or replays them.
+    ///
+    /// In `Mode::Record` the requests are sent by the transport as usual and every
+    /// request/response pair is written to `path`, replacing its content. In `Mode::Replay`
+    /// nothing is sent: each request is answered from `path`, and fails with
+    /// `Error::Transport` if it was not recorded. See the `cassette` module for how requests
+    /// are matched.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The cassette file.
+    /// * `mode` - Whether to record or to replay.
+    ///
+    /// # Returns
+    ///
+    /// The `DeepSeekClientBuilder` instance with the cassette configured.
+    /// ```ignore
+    /// let builder = DeepSeekClientBuilder::new("your_api_key".to_string())
+    ///     .with_cassette("tests/cassettes/weather.json", Mode::Replay);
+    /// ```
+    #[cfg(any(feature = "is_async", feature = "is_sync"))]
+    pub fn with_cassette(mut self, path: impl AsRef<Path>, mode: crate::cassette::Mode) -> Self {
+        self.cassette = Some((path.as_ref().to_path_buf(), mode));
+        self
+    }
+
+    /// Builds the async `DeepSeekClient` instance using the configured options.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the constructed `DeepSeekClient` on success, or an error
     /// if the client could not be built.
     ///
     /// # Errors
     ///
-    /// This method will return an error if the underlying `reqwest::blocking::DeepSeekClientBuilder`
-    /// fails to build the client.
+    /// This method will return `Error::Validation` if the API key cannot be sent as a header,
+    /// or `Error::Transport` if the default `reqwest::ClientBuilder` fails to build or the
+    /// cassette cannot be opened.
     ///
     /// # Examples
     ///
@@ -145,23 +309,99 @@ impl DeepSeekClientBuilder {
     ///     .build()
     ///     .expect("Failed to build client");
     /// ```
-    pub fn build(self) -> Result<DeepSeekClient> {
-        let mut headers = HeaderMap::new();
-        headers.insert("Authorization", format!("Bearer {}", self.api_key).parse()?);
-
-        let client_builder = ReqwestClientBuilder::new().default_headers(headers);
-        let client_builder = if let Some(secs) = self.timeout {
-            client_builder.timeout(Duration::from_secs(secs))
-        } else {
-            client_builder
+    #[cfg(feature = "is_async")]
+    pub fn build(self) -> Result<crate::r#async::DeepSeekClient> {
+        let headers = self.headers()?;
+        let transport = match self.transport {
+            Some(transport) => transport,
+            None => {
+                let client_builder = reqwest::ClientBuilder::new();
+                let client_builder = if let Some(secs) = self.timeout {
+                    client_builder.timeout(Duration::from_secs(secs))
+                } else {
+                    client_builder
+                };
+                Arc::new(crate::r#async::ReqwestTransport::new(
+                    client_builder.build()?,
+                ))
+            }
+        };
+        let transport = match &self.cassette {
+            Some((path, mode)) => Arc::new(crate::async_impl::cassette::CassetteTransport::open(
+                path, *mode, transport,
+            )?),
+            None => transport,
         };
 
-        let client = client_builder.build()?;
-        Ok(DeepSeekClient {
-            client,
+        Ok(crate::r#async::DeepSeekClient {
+            transport,
             host: self.host,
+            headers,
+            retry_policy: self.retry_policy,
+            rate_limiter: self.rate_limiter,
         })
     }
+
+    /// Builds the blocking `DeepSeekClient` instance using the configured options.
+    ///
+    /// The blocking client must not be built or used from within an async runtime.
+    ///
+    /// # Errors
+    ///
+    /// This method will return `Error::Validation` if the API key cannot be sent as a header,
+    /// or `Error::Transport` if the default `reqwest::blocking::ClientBuilder` fails to build
+    /// or the cassette cannot be opened.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let client = DeepSeekClientBuilder::new("your_api_key".to_string())
+    ///     .with_timeout(30)
+    ///     .build_blocking()
+    ///     .expect("Failed to build client");
+    /// ```
+    #[cfg(feature = "is_sync")]
+    pub fn build_blocking(self) -> Result<crate::blocking::DeepSeekClient> {
+        let headers = self.headers()?;
+        let transport = match self.blocking_transport {
+            Some(transport) => transport,
+            None => {
+                let client_builder = reqwest::blocking::ClientBuilder::new();
+                let client_builder = if let Some(secs) = self.timeout {
+                    client_builder.timeout(Duration::from_secs(secs))
+                } else {
+                    client_builder
+                };
+                Arc::new(crate::blocking::ReqwestTransport::new(
+                    client_builder.build()?,
+                ))
+            }
+        };
+        let transport = match &self.cassette {
+            Some((path, mode)) => Arc::new(crate::sync_impl::cassette::CassetteTransport::open(
+                path, *mode, transport,
+            )?),
+            None => transport,
+        };
+
+        Ok(crate::blocking::DeepSeekClient {
+            transport,
+            host: self.host,
+            headers,
+            retry_policy: self.retry_policy,
+            rate_limiter: self.rate_limiter,
+        })
+    }
+
+    #[cfg_attr(not(any(feature = "is_async", feature = "is_sync")), allow(dead_code))]
+    fn headers(&self) -> Result<HeaderMap> {
+        let mut headers = self.default_headers.clone();
+        let auth = format!("Bearer {}", self.api_key)
+            .parse()
+            .map_err(|_| ValidationError::new("api_key", "a valid header value", "<redacted>"))?;
+        headers.insert("Authorization", auth);
+        Ok(headers)
+    }
 }
 
 #[cfg(test)]
@@ -169,6 +409,17 @@ mod tests {
     use super::*;
     use std::env;
 
+    #[cfg_attr(
+        not(any(feature = "is_async", feature = "is_sync")),
+        allow(unused_variables)
+    )]
+    fn assert_builds(builder: DeepSeekClientBuilder) {
+        #[cfg(feature = "is_async")]
+        assert!(builder.clone().build().is_ok());
+        #[cfg(feature = "is_sync")]
+        assert!(builder.build_blocking().is_ok());
+    }
+
     #[test]
     fn test_deep_seek_client_builder_from_env_var() {
         env::set_var("DEEPSEEK_API_KEY", "test_api_key");
@@ -179,7 +430,7 @@ mod tests {
         assert_eq!(builder.timeout, Some(15));
         assert_eq!(builder.api_key, "test_api_key");
 
-        assert!(builder.build().is_ok());
+        assert_builds(builder);
     }
 
     #[test]
@@ -192,7 +443,7 @@ mod tests {
         assert_eq!(builder.host, "http://override.com");
         assert_eq!(builder.api_key, "another_test_api_keyu");
 
-        assert!(builder.build().is_ok());
+        assert_builds(builder);
     }
 
     #[test]
@@ -204,6 +455,46 @@ mod tests {
         assert_eq!(builder.timeout, Some(20));
         assert_eq!(builder.api_key, "test_api_key");
 
-        assert!(builder.build().is_ok());
+        assert_builds(builder);
+    }
+
+    #[test]
+    fn test_deep_seek_client_builder_retry_policy() {
+        let builder = DeepSeekClientBuilder::new("test_api_key".to_string());
+        assert_eq!(builder.retry_policy, RetryPolicy::never());
+
+        let policy = RetryPolicy::default().with_max_attempts(5);
+        let builder = builder.with_retry_policy(policy.clone());
+        assert_eq!(builder.retry_policy, policy);
+
+        assert_builds(builder);
+    }
+
+    #[test]
+    fn test_deep_seek_client_builder_default_headers() {
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert("x-team", "search".parse().unwrap());
+        default_headers.insert("authorization", "Bearer other".parse().unwrap());
+        let headers = DeepSeekClientBuilder::new("test_api_key".to_string())
+            .with_default_headers(default_headers)
+            .headers()
+            .unwrap();
+
+        assert_eq!(headers["x-team"], "search");
+        assert_eq!(headers["authorization"], "Bearer test_api_key");
+    }
+
+    #[test]
+    fn test_deep_seek_client_builder_invalid_api_key() {
+        let err = DeepSeekClientBuilder::new("bad\nkey".to_string())
+            .headers()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Validation(ValidationError {
+                parameter: "api_key",
+                ..
+            })
+        ));
     }
 }
@@ -0,0 +1,287 @@
+use crate::{
+    request::{
+        FrequencyPenalty, MaxToken, MessageRequest, PresencePenalty, ResponseFormat, ResponseType,
+        Stop, StreamOptions, SystemMessageRequest, Temperature, ToolChoice, ToolObject, TopP,
+    },
+    response::{ChatCompletion, ModelType},
+    CompletionsRequestBuilder, ValidationError,
+};
+
+/// An owned, multi-turn chat session.
+///
+/// A `Conversation` holds the system prompt, the messages exchanged so far, the tools the
+/// model may call and the sampling parameters used by every request. Sending it with
+/// `send`/`send_stream` (async client) or `send_blocking`/`send_stream_blocking` (blocking
+/// client) appends the assistant reply to the history, so the next user message can simply
+/// be pushed and sent again.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut conversation = Conversation::new()
+///     .with_system("You are a helpful assistant.")
+///     .temperature(0.7)?;
+///
+/// conversation.push(MessageRequest::user("Hello!"));
+/// let reply = conversation.send(&client).await?;
+///
+/// conversation.push(MessageRequest::user("Tell me more."));
+/// let reply = conversation.send(&client).await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Conversation {
+    messages: Vec<MessageRequest>,
+    tools: Vec<ToolObject>,
+    model: ModelType,
+    max_tokens: Option<MaxToken>,
+    response_format: Option<ResponseFormat>,
+    stop: Option<Stop>,
+    tool_choice: Option<ToolChoice>,
+    temperature: Option<Temperature>,
+    top_p: Option<TopP>,
+    presence_penalty: Option<PresencePenalty>,
+    frequency_penalty: Option<FrequencyPenalty>,
+    stream_options: Option<StreamOptions>,
+    keep_reasoning_content: bool,
+}
+
+impl Conversation {
+    /// Creates an empty `Conversation` using `ModelType::DeepSeekChat`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the system prompt, replacing the current one if any.
+    pub fn with_system(mut self, prompt: &str) -> Self {
+        let system = MessageRequest::System(SystemMessageRequest::new(prompt));
+        if self.system_len() == 1 {
+            self.messages[0] = system;
+        } else {
+            self.messages.insert(0, system);
+        }
+        self
+    }
+
+    /// Sets the tools the model may call.
+    pub fn with_tools(mut self, tools: Vec<ToolObject>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// Sets the model, either a `ModelType` shortcut or any model id.
+    pub fn use_model(mut self, model: impl Into<ModelType>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    pub fn max_tokens(mut self, value: u32) -> Result<Self, ValidationError> {
+        self.max_tokens = Some(MaxToken::new(value)?);
+        Ok(self)
+    }
+
+    pub fn response_format(mut self, value: ResponseType) -> Self {
+        self.response_format = Some(ResponseFormat { resp_type: value });
+        self
+    }
+
+    pub fn stop(mut self, value: Stop) -> Self {
+        self.stop = Some(value);
+        self
+    }
+
+    pub fn tool_choice(mut self, value: ToolChoice) -> Self {
+        self.tool_choice = Some(value);
+        self
+    }
+
+    pub fn temperature(mut self, value: f32) -> Result<Self, ValidationError> {
+        self.temperature = Some(Temperature::new(value)?);
+        Ok(self)
+    }
+
+    pub fn top_p(mut self, value: f32) -> Result<Self, ValidationError> {
+        self.top_p = Some(TopP::new(value)?);
+        Ok(self)
+    }
+
+    pub fn presence_penalty(mut self, value: f32) -> Result<Self, ValidationError> {
+        self.presence_penalty = Some(PresencePenalty::new(value)?);
+        Ok(self)
+    }
+
+    pub fn frequency_penalty(mut self, value: f32) -> Result<Self, ValidationError> {
+        self.frequency_penalty = Some(FrequencyPenalty::new(value)?);
+        Ok(self)
+    }
+
+    /// Sets the options of streaming requests, e.g. `StreamOptions::new(true)` to receive the
+    /// usage at the end of the stream of `send_stream`.
+    ///
+    /// They are left out of the requests of `send`, which do not stream.
+    pub fn stream_options(mut self, value: StreamOptions) -> Self {
+        self.stream_options = Some(value);
+        self
+    }
+
+    /// Sends the `reasoning_content` of prior assistant messages.
+    ///
+    /// It is kept in the history either way, but dropped from requests by default because
+    /// the DeepSeek API rejects it; see `CompletionsRequestBuilder::keep_reasoning_content`.
+    pub fn keep_reasoning_content(mut self, value: bool) -> Self {
+        self.keep_reasoning_content = value;
+        self
+    }
+
+    /// Returns all the messages, starting with the system prompt if one is set.
+    pub fn messages(&self) -> &[MessageRequest] {
+        &self.messages
+    }
+
+    /// Returns the tools the model may call.
+    pub fn tools(&self) -> &[ToolObject] {
+        &self.tools
+    }
+
+    /// Returns the number of messages, not counting the system prompt.
+    pub fn len(&self) -> usize {
+        self.messages.len() - self.system_len()
+    }
+
+    /// Returns `true` if no message besides the system prompt has been added.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a message, e.g. the next user message or the result of a tool call.
+    pub fn push(&mut self, message: MessageRequest) {
+        self.messages.push(message);
+    }
+
+    /// Appends the message of the first choice of `completion`, if it has one.
+    ///
+    /// `send` and `send_stream` call this with the reply of the model; call it yourself when
+    /// sending the request built by `request_builder`.
+    pub fn push_completion(&mut self, completion: &ChatCompletion) {
+        if let Some(message) = completion
+            .choices
+            .first()
+            .and_then(|choice| choice.message.clone())
+        {
+            self.messages.push(MessageRequest::Assistant(message));
+        }
+    }
+
+    /// Returns an independent copy of the conversation, to explore another continuation.
+    pub fn fork(&self) -> Self {
+        self.clone()
+    }
+
+    /// Keeps the system prompt and the first `n` other messages, dropping the rest.
+    pub fn truncate_to(&mut self, n: usize) {
+        self.messages.truncate(self.system_len() + n);
+    }
+
+    /// Removes the last user message and every message after it.
+    ///
+    /// # Returns
+    ///
+    /// The removed messages, oldest first, or an empty `Vec` if there is no user message.
+    pub fn undo_last_turn(&mut self) -> Vec<MessageRequest> {
+        match self
+            .messages
+            .iter()
+            .rposition(|message| matches!(message, MessageRequest::User(_)))
+        {
+            Some(index) => self.messages.split_off(index),
+            None => Vec::new(),
+        }
+    }
+
+    /// Builds a request for the whole conversation with its tools and sampling parameters.
+    ///
+    /// The reply is not appended to the conversation; use `push_completion` for that. When
+    /// `stream_options` is set, the builder must be sent with `stream(true)`.
+    pub fn request_builder(&self) -> CompletionsRequestBuilder<'_> {
+        let mut builder =
+            CompletionsRequestBuilder::new(&self.messages).use_model(self.model.clone());
+        if !self.tools.is_empty() {
+            builder = builder.tools(&self.tools);
+        }
+        builder.max_tokens = self.max_tokens.clone();
+        builder.response_format = self.response_format.clone();
+        builder.stop = self.stop.clone();
+        builder.tool_choice = self.tool_choice.clone();
+        builder.temperature = self.temperature.clone();
+        builder.top_p = self.top_p.clone();
+        builder.presence_penalty = self.presence_penalty.clone();
+        builder.frequency_penalty = self.frequency_penalty.clone();
+        builder.stream_options = self.stream_options.clone();
+        builder.keep_reasoning_content(self.keep_reasoning_content)
+    }
+
+    fn system_len(&self) -> usize {
+        usize::from(matches!(
+            self.messages.first(),
+            Some(MessageRequest::System(_))
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{response::AssistantMessage, RequestBuilder};
+
+    fn conversation() -> Conversation {
+        let mut conversation = Conversation::new().with_system("Be brief.");
+        conversation.push(MessageRequest::user("Hi"));
+        conversation.push(MessageRequest::Assistant(AssistantMessage::new("Hello")));
+        conversation.push(MessageRequest::user("Bye"));
+        conversation.push(MessageRequest::Assistant(AssistantMessage::new("Bye")));
+        conversation
+    }
+
+    #[test]
+    fn test_system_prompt_is_kept() {
+        let mut conversation = conversation().with_system("Be verbose.");
+        assert_eq!(conversation.messages()[0].get_content(), "Be verbose.");
+        assert_eq!(conversation.len(), 4);
+
+        conversation.truncate_to(1);
+        assert_eq!(conversation.messages().len(), 2);
+        assert_eq!(conversation.messages()[1].get_content(), "Hi");
+    }
+
+    #[test]
+    fn test_fork_and_undo_last_turn() {
+        let mut conversation = conversation();
+        let fork = conversation.fork();
+
+        let removed = conversation.undo_last_turn();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(removed[0].get_content(), "Bye");
+        assert_eq!(conversation.len(), 2);
+        assert_eq!(fork.len(), 4);
+
+        conversation.undo_last_turn();
+        assert!(conversation.is_empty());
+        assert!(conversation.undo_last_turn().is_empty());
+    }
+
+    #[test]
+    fn test_request_builder_uses_defaults() {
+        let conversation = conversation()
+            .use_model(ModelType::DeepSeekReasoner)
+            .max_tokens(100)
+            .unwrap()
+            .temperature(0.5)
+            .unwrap();
+        let request = conversation.request_builder().build();
+
+        assert_eq!(request.messages.len(), 5);
+        assert_eq!(request.model, ModelType::DeepSeekReasoner);
+        assert_eq!(request.max_tokens, Some(MaxToken(100)));
+        assert_eq!(request.temperature, Some(Temperature(0.5)));
+        assert!(request.tools.is_none());
+    }
+}
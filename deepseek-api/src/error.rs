@@ -1,29 +1,742 @@
+use crate::retry::retry_after;
+use http::HeaderMap;
+use serde::Deserialize;
+use serde_json::Value;
 use std::fmt;
+use std::time::Duration;
+
+/// A `Result` alias where the `Err` case is `deepseek_api::Error`.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The error type returned by every fallible operation of this crate.
+#[derive(Debug)]
+pub enum Error {
+    /// A request parameter was rejected before anything was sent.
+    Validation(ValidationError),
+    /// A request was rejected by `RequestBuilder::validate` before it was sent.
+    InvalidRequest(ValidationErrors),
+    /// The request could not be sent, or the response could not be read.
+    Transport(TransportError),
+    /// The server answered with an unsuccessful status.
+    Api(ApiError),
+    /// A streaming response could not be decoded.
+    Stream(StreamError),
+    /// A response body could not be deserialized into the expected type.
+    Deserialize(serde_json::Error),
+    /// The arguments of a tool call sent by the model are not valid for the tool.
+    ToolArguments(ToolArgumentsError),
+    /// The model was still calling tools after the given number of requests.
+    TooManyToolIterations(usize),
+    /// The reply of the model could not be decoded into the requested type.
+    StructuredOutput(StructuredOutputError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Validation(err) => write!(f, "Validation Error: {}", err),
+            Error::InvalidRequest(err) => write!(f, "Validation Error: {}", err),
+            Error::Transport(err) => write!(f, "Transport Error: {}", err),
+            Error::Api(err) => write!(f, "{}", err),
+            Error::Stream(err) => write!(f, "Stream Error: {}", err),
+            Error::Deserialize(err) => write!(f, "Deserialize Error: {}", err),
+            Error::ToolArguments(err) => write!(f, "Tool Arguments Error: {}", err),
+            Error::TooManyToolIterations(iterations) => write!(
+                f,
+                "Tool Loop Error: the model still called tools after {} requests",
+                iterations
+            ),
+            Error::StructuredOutput(err) => write!(f, "Structured Output Error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Validation(err) => Some(err),
+            Error::InvalidRequest(err) => Some(err),
+            Error::Transport(err) => Some(err),
+            Error::Api(err) => Some(err),
+            Error::Stream(err) => Some(err),
+            Error::Deserialize(err) => Some(err),
+            Error::ToolArguments(err) => Some(err),
+            Error::TooManyToolIterations(_) => None,
+            Error::StructuredOutput(err) => Some(err),
+        }
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(err: ValidationError) -> Self {
+        Error::Validation(err)
+    }
+}
+
+impl From<ValidationErrors> for Error {
+    fn from(err: ValidationErrors) -> Self {
+        Error::InvalidRequest(err)
+    }
+}
+
+impl From<TransportError> for Error {
+    fn from(err: TransportError) -> Self {
+        Error::Transport(err)
+    }
+}
+
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Transport(err.into())
+    }
+}
+
+impl From<ApiError> for Error {
+    fn from(err: ApiError) -> Self {
+        Error::Api(err)
+    }
+}
+
+impl From<StreamError> for Error {
+    fn from(err: StreamError) -> Self {
+        Error::Stream(err)
+    }
+}
+
+impl From<ToolArgumentsError> for Error {
+    fn from(err: ToolArgumentsError) -> Self {
+        Error::ToolArguments(err)
+    }
+}
+
+impl From<StructuredOutputError> for Error {
+    fn from(err: StructuredOutputError) -> Self {
+        Error::StructuredOutput(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Deserialize(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    /// Reading a response body through `std::io` wraps transport failures in an
+    /// `io::Error`; those are unwrapped back into `Error::Transport`.
+    fn from(err: std::io::Error) -> Self {
+        let is_transport = err.get_ref().is_some_and(|inner| {
+            #[cfg(any(feature = "is_async", feature = "is_sync"))]
+            if inner.is::<reqwest::Error>() {
+                return true;
+            }
+            inner.is::<TransportError>()
+        });
+        if !is_transport {
+            return Error::Stream(StreamError::Io(err));
+        }
+
+        let inner = err
+            .into_inner()
+            .expect("io error was checked to wrap an error");
+        match inner.downcast::<TransportError>() {
+            Ok(err) => Error::Transport(*err),
+            #[cfg(any(feature = "is_async", feature = "is_sync"))]
+            Err(inner) => match inner.downcast::<reqwest::Error>() {
+                Ok(err) => Error::Transport((*err).into()),
+                Err(_) => unreachable!("io error was checked to wrap a transport error"),
+            },
+            #[cfg(not(any(feature = "is_async", feature = "is_sync")))]
+            Err(_) => unreachable!("io error was checked to wrap a transport error"),
+        }
+    }
+}
+
+/// The broad cause of a `TransportError`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportErrorKind {
+    /// The connection to the server could not be established.
+    Connect,
+    /// The request or the response took too long.
+    Timeout,
+    /// Any other failure.
+    Other,
+}
+
+/// A failure reported by a transport while exchanging a request with the server.
+#[derive(Debug)]
+pub struct TransportError {
+    kind: TransportErrorKind,
+    source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl TransportError {
+    /// Creates a new `TransportError`.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The broad cause of the error, used to decide whether to retry.
+    /// * `source` - The underlying error.
+    pub fn new(
+        kind: TransportErrorKind,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
+        TransportError {
+            kind,
+            source: source.into(),
+        }
+    }
+
+    /// Returns the broad cause of the error.
+    pub fn kind(&self) -> TransportErrorKind {
+        self.kind
+    }
+
+    /// Returns `true` if the connection to the server could not be established.
+    pub fn is_connect(&self) -> bool {
+        self.kind == TransportErrorKind::Connect
+    }
+
+    /// Returns `true` if the request or the response took too long.
+    pub fn is_timeout(&self) -> bool {
+        self.kind == TransportErrorKind::Timeout
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl std::error::Error for TransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
+impl From<reqwest::Error> for TransportError {
+    fn from(err: reqwest::Error) -> Self {
+        let kind = if err.is_connect() {
+            TransportErrorKind::Connect
+        } else if err.is_timeout() {
+            TransportErrorKind::Timeout
+        } else {
+            TransportErrorKind::Other
+        };
+        TransportError::new(kind, err)
+    }
+}
+
+/// A request parameter that is outside the range accepted by the API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    /// Name of the parameter, as sent to the API.
+    pub parameter: &'static str,
+    /// The accepted range, e.g. `between -2 and 2`.
+    pub range: String,
+    /// The rejected value.
+    pub value: String,
+}
+
+impl ValidationError {
+    /// Creates a new `ValidationError`.
+    ///
+    /// # Arguments
+    ///
+    /// * `parameter` - Name of the parameter, as sent to the API.
+    /// * `range` - The accepted range.
+    /// * `value` - The rejected value.
+    pub fn new(
+        parameter: &'static str,
+        range: impl Into<String>,
+        value: impl fmt::Display,
+    ) -> Self {
+        ValidationError {
+            parameter,
+            range: range.into(),
+            value: value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} must be {}, got {}",
+            self.parameter, self.range, self.value
+        )
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Every problem found in a request by `RequestBuilder::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl ValidationErrors {
+    /// Returns `Ok(())` if no problem was found, and the problems otherwise.
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Returns `true` if `parameter` has a problem.
+    pub fn contains(&self, parameter: &str) -> bool {
+        self.0.iter().any(|err| err.parameter == parameter)
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, err) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", err)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// The arguments of a tool call could not be decoded into the arguments type of the tool.
+///
+/// Models occasionally produce truncated or otherwise malformed JSON, so the raw arguments
+/// are kept to be logged or sent back to the model.
+#[derive(Debug)]
+pub struct ToolArgumentsError {
+    /// Name of the function the model called.
+    pub name: String,
+    /// The arguments sent by the model.
+    pub arguments: String,
+    /// The underlying deserialization error.
+    pub source: serde_json::Error,
+}
+
+impl fmt::Display for ToolArgumentsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid arguments for `{}`: {}, got {}",
+            self.name, self.source, self.arguments
+        )
+    }
+}
+
+impl std::error::Error for ToolArgumentsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// The replies of the model could not be decoded into the type requested from
+/// `complete_json`, even after asking it to repair them.
 #[derive(Debug)]
+pub struct StructuredOutputError {
+    /// The number of requests sent.
+    pub attempts: usize,
+    /// The last reply of the model.
+    pub raw: String,
+    /// The error decoding the last reply.
+    pub source: serde_json::Error,
+}
+
+impl fmt::Display for StructuredOutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid reply after {} attempts: {}, got {}",
+            self.attempts, self.source, self.raw
+        )
+    }
+}
+
+impl std::error::Error for StructuredOutputError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// A failure while decoding a server-sent event stream.
+#[derive(Debug)]
+pub enum StreamError {
+    /// The data of an event is not valid JSON for the expected type.
+    Json {
+        /// The data that failed to decode.
+        data: String,
+        /// The underlying deserialization error.
+        source: serde_json::Error,
+    },
+    /// The stream could not be read.
+    Io(std::io::Error),
+    /// The stream ended before a complete response could be assembled from it.
+    Incomplete(String),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Json { data, source } => {
+                write!(f, "invalid JSON in stream event `{}`: {}", data, source)
+            }
+            StreamError::Io(err) => write!(f, "{}", err),
+            StreamError::Incomplete(reason) => write!(f, "stream ended early: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StreamError::Json { source, .. } => Some(source),
+            StreamError::Io(err) => Some(err),
+            StreamError::Incomplete(_) => None,
+        }
+    }
+}
+
+/// Headers that may carry the id the server assigned to a request, checked in order.
+const REQUEST_ID_HEADERS: [&str; 2] = ["x-request-id", "x-ds-trace-id"];
+
+/// Details of an error response returned by the DeepSeek API.
+///
+/// The API reports errors as `{"error": {"message": ..., "type": ..., "code": ...}}`.
+/// When the body does not follow that envelope, `message` holds the raw body.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ErrorResponse {
+    /// HTTP status code of the response.
+    pub status: u16,
+    /// Human readable description of the error.
+    pub message: String,
+    /// The `type` field of the error envelope.
+    pub error_type: Option<String>,
+    /// The `code` field of the error envelope.
+    pub code: Option<String>,
+    /// The id assigned to the request by the server.
+    pub request_id: Option<String>,
+    /// The delay requested by the `Retry-After` header.
+    pub retry_after: Option<Duration>,
+    /// The raw response body.
+    pub body: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    error: ErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default, rename = "type")]
+    error_type: Option<String>,
+    #[serde(default)]
+    code: Option<Value>,
+}
+
+impl ErrorResponse {
+    /// Builds an `ErrorResponse` from the parts of an HTTP response.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The HTTP status code.
+    /// * `headers` - The response headers, used for the request id and `Retry-After`.
+    /// * `body` - The response body.
+    pub fn new(status: u16, headers: &HeaderMap, body: String) -> Self {
+        let request_id = REQUEST_ID_HEADERS
+            .iter()
+            .find_map(|name| headers.get(*name)?.to_str().ok())
+            .map(str::to_string);
+
+        let mut resp = ErrorResponse {
+            status,
+            message: body.clone(),
+            request_id,
+            retry_after: retry_after(headers),
+            ..Default::default()
+        };
+
+        if let Ok(envelope) = serde_json::from_str::<ErrorEnvelope>(&body) {
+            if let Some(message) = envelope.error.message {
+                resp.message = message;
+            }
+            resp.error_type = envelope.error.error_type;
+            resp.code = envelope.error.code.and_then(|code| match code {
+                Value::String(code) => Some(code),
+                Value::Null => None,
+                code => Some(code.to_string()),
+            });
+        }
+        resp.body = body;
+        resp
+    }
+}
+
+/// An error response returned by the DeepSeek API, classified by HTTP status.
+///
+/// See the [DeepSeek error codes](https://api-docs.deepseek.com/quick_start/error_codes).
+#[derive(Debug, Clone, PartialEq)]
 pub enum ApiError {
-    Unknown(String),
-    BadRequest(String),
-    Unauthorized(String),
-    InsufficientFunds(String),
-    InvalidParameters(String),
-    RateLimitExceeded(String),
-    ServerError(String),
-    ServiceUnavailable(String),
+    /// `400`: the request body is malformed.
+    BadRequest(Box<ErrorResponse>),
+    /// `401`: the API key is wrong.
+    Unauthorized(Box<ErrorResponse>),
+    /// `402`: the account has run out of balance.
+    InsufficientFunds(Box<ErrorResponse>),
+    /// `403`: the API key is not allowed to access the resource.
+    Forbidden(Box<ErrorResponse>),
+    /// `404`: the endpoint does not exist.
+    NotFound(Box<ErrorResponse>),
+    /// `408`: the server timed out waiting for the request.
+    RequestTimeout(Box<ErrorResponse>),
+    /// `422`: the request contains invalid parameters.
+    InvalidParameters(Box<ErrorResponse>),
+    /// `429`: requests are sent too quickly.
+    RateLimitExceeded(Box<ErrorResponse>),
+    /// `500`: the server encountered an issue.
+    ServerError(Box<ErrorResponse>),
+    /// `502`: an upstream server returned an invalid response.
+    BadGateway(Box<ErrorResponse>),
+    /// `503`: the server is overloaded.
+    ServiceUnavailable(Box<ErrorResponse>),
+    /// `504`: an upstream server timed out.
+    GatewayTimeout(Box<ErrorResponse>),
+    /// Any other unsuccessful status.
+    Unknown(Box<ErrorResponse>),
+}
+
+impl From<ErrorResponse> for ApiError {
+    fn from(resp: ErrorResponse) -> Self {
+        let resp = Box::new(resp);
+        match resp.status {
+            400 => ApiError::BadRequest(resp),
+            401 => ApiError::Unauthorized(resp),
+            402 => ApiError::InsufficientFunds(resp),
+            403 => ApiError::Forbidden(resp),
+            404 => ApiError::NotFound(resp),
+            408 => ApiError::RequestTimeout(resp),
+            422 => ApiError::InvalidParameters(resp),
+            429 => ApiError::RateLimitExceeded(resp),
+            500 => ApiError::ServerError(resp),
+            502 => ApiError::BadGateway(resp),
+            503 => ApiError::ServiceUnavailable(resp),
+            504 => ApiError::GatewayTimeout(resp),
+            _ => ApiError::Unknown(resp),
+        }
+    }
 }
+
 impl fmt::Display for ApiError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let description = match self {
-            ApiError::Unknown(msg) => format!("Unknown Error: {}", msg),
-            ApiError::BadRequest(msg) => format!("Bad Request: {}", msg),
-            ApiError::Unauthorized(msg) => format!("Unauthorized: {}", msg),
-            ApiError::InsufficientFunds(msg) => format!("Insufficient Funds: {}", msg),
-            ApiError::InvalidParameters(msg) => format!("Invalid Parameters: {}", msg),
-            ApiError::RateLimitExceeded(msg) => format!("Rate Limit Exceeded: {}", msg),
-            ApiError::ServerError(msg) => format!("Server Error: {}", msg),
-            ApiError::ServiceUnavailable(msg) => format!("Service Unavailable: {}", msg),
+            ApiError::BadRequest(_) => "Bad Request",
+            ApiError::Unauthorized(_) => "Unauthorized",
+            ApiError::InsufficientFunds(_) => "Insufficient Funds",
+            ApiError::Forbidden(_) => "Forbidden",
+            ApiError::NotFound(_) => "Not Found",
+            ApiError::RequestTimeout(_) => "Request Timeout",
+            ApiError::InvalidParameters(_) => "Invalid Parameters",
+            ApiError::RateLimitExceeded(_) => "Rate Limit Exceeded",
+            ApiError::ServerError(_) => "Server Error",
+            ApiError::BadGateway(_) => "Bad Gateway",
+            ApiError::ServiceUnavailable(_) => "Service Unavailable",
+            ApiError::GatewayTimeout(_) => "Gateway Timeout",
+            ApiError::Unknown(_) => "Unknown Error",
         };
-        write!(f, "{}", description)
+        let resp = self.response();
+        write!(f, "{} ({}): {}", description, resp.status, resp.message)?;
+        if let Some(request_id) = &resp.request_id {
+            write!(f, " [request id: {}]", request_id)?;
+        }
+        Ok(())
     }
 }
 
 impl std::error::Error for ApiError {}
+
+impl ApiError {
+    /// Returns the details of the error response.
+    pub fn response(&self) -> &ErrorResponse {
+        match self {
+            ApiError::BadRequest(resp)
+            | ApiError::Unauthorized(resp)
+            | ApiError::InsufficientFunds(resp)
+            | ApiError::Forbidden(resp)
+            | ApiError::NotFound(resp)
+            | ApiError::RequestTimeout(resp)
+            | ApiError::InvalidParameters(resp)
+            | ApiError::RateLimitExceeded(resp)
+            | ApiError::ServerError(resp)
+            | ApiError::BadGateway(resp)
+            | ApiError::ServiceUnavailable(resp)
+            | ApiError::GatewayTimeout(resp)
+            | ApiError::Unknown(resp) => resp,
+        }
+    }
+
+    /// Returns the HTTP status code of the response.
+    pub fn status(&self) -> u16 {
+        self.response().status
+    }
+
+    /// Returns the error message sent by the server.
+    pub fn message(&self) -> &str {
+        &self.response().message
+    }
+
+    /// Returns the id the server assigned to the failed request, if any.
+    pub fn request_id(&self) -> Option<&str> {
+        self.response().request_id.as_deref()
+    }
+
+    /// Returns the delay requested by the server before retrying, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.response().retry_after
+    }
+
+    /// Returns `true` if the same request may succeed when sent again later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ApiError::RequestTimeout(_)
+                | ApiError::RateLimitExceeded(_)
+                | ApiError::ServerError(_)
+                | ApiError::BadGateway(_)
+                | ApiError::ServiceUnavailable(_)
+                | ApiError::GatewayTimeout(_)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::RETRY_AFTER;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_validation_error_message() {
+        let err = Error::from(ValidationError::new(
+            "frequency_penalty",
+            "between -2 and 2",
+            3.5,
+        ));
+
+        assert!(matches!(
+            &err,
+            Error::Validation(ValidationError {
+                parameter: "frequency_penalty",
+                ..
+            })
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Validation Error: frequency_penalty must be between -2 and 2, got 3.5"
+        );
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn test_source_chain() {
+        let json_err = serde_json::from_str::<u32>("x").unwrap_err();
+        let err = Error::from(StreamError::Json {
+            data: "x".to_string(),
+            source: json_err,
+        });
+
+        let stream_err = err.source().unwrap();
+        assert!(stream_err.is::<StreamError>());
+        assert!(stream_err.source().unwrap().is::<serde_json::Error>());
+    }
+
+    #[test]
+    fn test_io_error_unwraps_transport_error() {
+        let transport = TransportError::new(TransportErrorKind::Connect, "connection refused");
+        let err = Error::from(std::io::Error::other(transport));
+        assert!(matches!(&err, Error::Transport(err) if err.is_connect()));
+
+        let err = Error::from(std::io::Error::other("disk on fire"));
+        assert!(matches!(err, Error::Stream(StreamError::Io(_))));
+    }
+
+    #[test]
+    fn test_parse_error_envelope() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", "req-123".parse().unwrap());
+        headers.insert(RETRY_AFTER, "5".parse().unwrap());
+        let body = r#"{"error":{"message":"Rate limit reached","type":"rate_limit_error","code":"rate_limited"}}"#;
+
+        let err = ApiError::from(ErrorResponse::new(429, &headers, body.to_string()));
+
+        assert!(matches!(err, ApiError::RateLimitExceeded(_)));
+        assert!(err.is_retryable());
+        assert_eq!(err.status(), 429);
+        assert_eq!(err.message(), "Rate limit reached");
+        assert_eq!(err.request_id(), Some("req-123"));
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(5)));
+        assert_eq!(
+            err.response().error_type.as_deref(),
+            Some("rate_limit_error")
+        );
+        assert_eq!(err.response().code.as_deref(), Some("rate_limited"));
+        assert_eq!(err.response().body, body);
+        assert_eq!(
+            err.to_string(),
+            "Rate Limit Exceeded (429): Rate limit reached [request id: req-123]"
+        );
+    }
+
+    #[test]
+    fn test_parse_non_json_body() {
+        let err = ApiError::from(ErrorResponse::new(
+            502,
+            &HeaderMap::new(),
+            "<html>bad gateway</html>".to_string(),
+        ));
+
+        assert!(matches!(err, ApiError::BadGateway(_)));
+        assert_eq!(err.message(), "<html>bad gateway</html>");
+        assert!(err.response().error_type.is_none());
+        assert!(err.request_id().is_none());
+    }
+
+    #[test]
+    fn test_numeric_code() {
+        let body = r#"{"error":{"message":"invalid","type":"invalid_request_error","code":1001}}"#;
+        let resp = ErrorResponse::new(400, &HeaderMap::new(), body.to_string());
+        assert_eq!(resp.code.as_deref(), Some("1001"));
+    }
+
+    #[test]
+    fn test_retryable_classification() {
+        let cases = [
+            (400, false),
+            (401, false),
+            (402, false),
+            (403, false),
+            (404, false),
+            (408, true),
+            (422, false),
+            (429, true),
+            (500, true),
+            (502, true),
+            (503, true),
+            (504, true),
+            (418, false),
+        ];
+        for (status, retryable) in cases {
+            let err = ApiError::from(ErrorResponse::new(status, &HeaderMap::new(), String::new()));
+            assert_eq!(err.is_retryable(), retryable, "status {}", status);
+        }
+    }
+}
@@ -1,22 +1,63 @@
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
+mod batch;
+mod capabilities;
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
+pub mod cassette;
 mod client_builder;
+mod conversation;
 mod error;
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
+mod rate_limit;
 pub mod request;
 mod request_builder;
 pub mod response;
+mod retry;
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
+mod sse;
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
+mod structured;
+mod tokenizer;
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
+pub use batch::*;
+pub use capabilities::*;
 pub use client_builder::*;
+pub use conversation::*;
 pub use error::*;
+#[cfg(any(feature = "is_async", feature = "is_sync"))]
+pub use rate_limit::*;
 pub use request_builder::*;
+pub use retry::*;
+pub use tokenizer::*;
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "is_sync")] {
-        mod sync_impl;
+#[cfg(feature = "is_async")]
+mod async_impl;
+#[cfg(feature = "is_sync")]
+mod sync_impl;
 
-        pub use sync_impl::json_stream;
-        pub use sync_impl::client::*;
-    } else {
-        mod async_impl;
+/// The asynchronous client, enabled by the `is_async` feature (on by default).
+#[cfg(feature = "is_async")]
+pub mod r#async {
+    pub use crate::async_impl::batch::*;
+    pub use crate::async_impl::client::*;
+    pub use crate::async_impl::conversation::*;
+    pub use crate::async_impl::json_stream;
+    pub use crate::async_impl::tools::*;
+    pub use crate::async_impl::transport::*;
+}
 
-        pub use async_impl::json_stream;
-        pub use async_impl::client::*;
-    }
+/// The blocking client, enabled by the `is_sync` feature.
+///
+/// Both clients can be enabled at the same time; enabling `is_sync` never changes what the
+/// crate root exports.
+#[cfg(feature = "is_sync")]
+pub mod blocking {
+    pub use crate::sync_impl::batch::*;
+    pub use crate::sync_impl::client::*;
+    pub use crate::sync_impl::conversation::*;
+    pub use crate::sync_impl::json_stream;
+    pub use crate::sync_impl::tools::*;
+    pub use crate::sync_impl::transport::*;
 }
+
+#[cfg(feature = "is_async")]
+pub use r#async::{json_stream, DeepSeekClient, ToolRegistry};
@@ -0,0 +1,477 @@
+use crate::response::Usage;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::Waker;
+use std::time::{Duration, Instant};
+
+/// Client-side limits on the requests sent by a `DeepSeekClient`.
+///
+/// The limits are shared by every clone of the client, and by every client built from the
+/// same `DeepSeekClientBuilder`, so a batch job can hand clones to many tasks and still stay
+/// within the quota of its API key. Requests waiting for capacity are served in the order
+/// they arrived: the async client queues them without blocking the runtime, and the
+/// blocking client parks the calling thread.
+///
+/// Each attempt of a request counts, retries included. The tokens of a request are first
+/// estimated from the size of its body, then corrected with the `Usage` reported by the
+/// server once the response (or the stream) has been read. A request stays in flight
+/// until its response body has been read or dropped.
+///
+/// # Examples
+///
+/// ```ignore
+/// let client = DeepSeekClientBuilder::new("your_api_key".to_string())
+///     .with_rate_limit(
+///         RateLimit::default()
+///             .with_requests_per_minute(60)
+///             .with_tokens_per_minute(100_000)
+///             .with_max_in_flight(8),
+///     )
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    requests_per_minute: Option<u32>,
+    tokens_per_minute: Option<u32>,
+    max_in_flight: Option<u32>,
+    window: Duration,
+}
+
+impl Default for RateLimit {
+    /// Returns a limit that lets every request through.
+    fn default() -> Self {
+        RateLimit {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            max_in_flight: None,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RateLimit {
+    /// Sets the maximum number of requests started in any minute. `0` is treated as `1`.
+    pub fn with_requests_per_minute(mut self, requests: u32) -> Self {
+        self.requests_per_minute = Some(requests.max(1));
+        self
+    }
+
+    /// Sets the maximum number of tokens, prompt and completion together, of the requests
+    /// started in any minute.
+    ///
+    /// A request estimated to need more than the whole budget is sent alone.
+    pub fn with_tokens_per_minute(mut self, tokens: u32) -> Self {
+        self.tokens_per_minute = Some(tokens);
+        self
+    }
+
+    /// Sets the maximum number of requests in flight at once. `0` is treated as `1`.
+    pub fn with_max_in_flight(mut self, requests: u32) -> Self {
+        self.max_in_flight = Some(requests.max(1));
+        self
+    }
+
+    /// Returns the maximum number of requests started in any minute, if limited.
+    pub fn requests_per_minute(&self) -> Option<u32> {
+        self.requests_per_minute
+    }
+
+    /// Returns the maximum number of tokens of the requests started in any minute, if limited.
+    pub fn tokens_per_minute(&self) -> Option<u32> {
+        self.tokens_per_minute
+    }
+
+    /// Returns the maximum number of requests in flight at once, if limited.
+    pub fn max_in_flight(&self) -> Option<u32> {
+        self.max_in_flight
+    }
+
+    #[cfg(all(test, feature = "is_sync"))]
+    pub(crate) fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+}
+
+/// Estimates the tokens of a request from the size of its body, about 4 bytes per token.
+pub(crate) fn estimate_tokens(body: &[u8]) -> u32 {
+    u32::try_from(body.len().div_ceil(4)).unwrap_or(u32::MAX)
+}
+
+/// Why a request cannot start yet.
+enum Wait {
+    /// Until another request finishes or leaves the queue.
+    Capacity,
+    /// Until the given time, when older requests leave the window.
+    Until(Instant),
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: u64,
+    started: Instant,
+    tokens: u32,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_id: u64,
+    queue: VecDeque<u64>,
+    in_flight: u32,
+    window: VecDeque<Entry>,
+    wakers: Vec<Waker>,
+}
+
+/// Enforces a `RateLimit` for the clients sharing it.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            state: Mutex::new(State::default()),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Waits until a request of `tokens` estimated tokens can start.
+    #[cfg(feature = "is_async")]
+    pub(crate) async fn acquire(self: &Arc<Self>, tokens: u32) -> Permit {
+        use std::future::Future;
+        use std::task::Poll;
+
+        let ticket = self.enqueue();
+        let mut sleep: Option<std::pin::Pin<Box<tokio::time::Sleep>>> = None;
+        std::future::poll_fn(|cx| loop {
+            let wait = {
+                let mut state = self.lock();
+                match self.try_acquire(&mut state, &ticket, tokens) {
+                    Ok(permit) => return Poll::Ready(permit),
+                    Err(wait) => {
+                        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                            state.wakers.push(cx.waker().clone());
+                        }
+                        wait
+                    }
+                }
+            };
+            let Wait::Until(deadline) = wait else {
+                return Poll::Pending;
+            };
+            let deadline = tokio::time::Instant::from_std(deadline);
+            let sleep = sleep.get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+            if sleep.deadline() != deadline {
+                sleep.as_mut().reset(deadline);
+            }
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        })
+        .await
+    }
+
+    /// Parks the calling thread until a request of `tokens` estimated tokens can start.
+    #[cfg(feature = "is_sync")]
+    pub(crate) fn acquire_blocking(self: &Arc<Self>, tokens: u32) -> Permit {
+        let ticket = self.enqueue();
+        let mut state = self.lock();
+        loop {
+            state = match self.try_acquire(&mut state, &ticket, tokens) {
+                Ok(permit) => return permit,
+                Err(Wait::Capacity) => self
+                    .condvar
+                    .wait(state)
+                    .unwrap_or_else(|err| err.into_inner()),
+                Err(Wait::Until(deadline)) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    self.condvar
+                        .wait_timeout(state, timeout)
+                        .unwrap_or_else(|err| err.into_inner())
+                        .0
+                }
+            };
+        }
+    }
+
+    fn enqueue(self: &Arc<Self>) -> Ticket {
+        let mut state = self.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.queue.push_back(id);
+        Ticket {
+            limiter: Arc::clone(self),
+            id,
+        }
+    }
+
+    /// Starts the request of `ticket` if it is first in the queue and the limits allow it.
+    fn try_acquire(
+        self: &Arc<Self>,
+        state: &mut State,
+        ticket: &Ticket,
+        tokens: u32,
+    ) -> Result<Permit, Wait> {
+        let now = Instant::now();
+        while state
+            .window
+            .front()
+            .is_some_and(|entry| now.duration_since(entry.started) >= self.limit.window)
+        {
+            state.window.pop_front();
+        }
+
+        if state.queue.front() != Some(&ticket.id) {
+            return Err(Wait::Capacity);
+        }
+        if self
+            .limit
+            .max_in_flight
+            .is_some_and(|max| state.in_flight >= max)
+        {
+            return Err(Wait::Capacity);
+        }
+        if let Some(max) = self.limit.requests_per_minute {
+            if state.window.len() >= max as usize {
+                let index = state.window.len() - max as usize;
+                return Err(Wait::Until(state.window[index].started + self.limit.window));
+            }
+        }
+        if let Some(max) = self.limit.tokens_per_minute {
+            let mut used: u64 = state.window.iter().map(|entry| entry.tokens as u64).sum();
+            if !state.window.is_empty() && used + tokens as u64 > max as u64 {
+                let mut until = now;
+                for entry in &state.window {
+                    used -= entry.tokens as u64;
+                    until = entry.started + self.limit.window;
+                    if used + tokens as u64 <= max as u64 {
+                        break;
+                    }
+                }
+                return Err(Wait::Until(until));
+            }
+        }
+
+        state.queue.pop_front();
+        state.in_flight += 1;
+        state.window.push_back(Entry {
+            id: ticket.id,
+            started: now,
+            tokens,
+        });
+        self.wake_all(state);
+        Ok(Permit {
+            inner: Arc::new(PermitInner {
+                limiter: Arc::clone(self),
+                id: ticket.id,
+            }),
+        })
+    }
+
+    /// Returns the number of requests in flight and the tokens of those in the window.
+    #[cfg(test)]
+    pub(crate) fn snapshot(&self) -> (u32, Vec<u32>) {
+        let state = self.lock();
+        let tokens = state.window.iter().map(|entry| entry.tokens).collect();
+        (state.in_flight, tokens)
+    }
+
+    fn wake_all(&self, state: &mut State) {
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+        self.condvar.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// A place in the queue of a `RateLimiter`, left when dropped.
+struct Ticket {
+    limiter: Arc<RateLimiter>,
+    id: u64,
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut state = self.limiter.lock();
+        if let Some(index) = state.queue.iter().position(|id| *id == self.id) {
+            state.queue.remove(index);
+            self.limiter.wake_all(&mut state);
+        }
+    }
+}
+
+/// The right of a started request to be in flight, released when the last clone is dropped.
+#[derive(Debug, Clone)]
+pub(crate) struct Permit {
+    inner: Arc<PermitInner>,
+}
+
+#[derive(Debug)]
+struct PermitInner {
+    limiter: Arc<RateLimiter>,
+    id: u64,
+}
+
+impl Permit {
+    /// Replaces the estimated tokens of the request with those reported by the server.
+    pub(crate) fn record_usage(&self, usage: &Usage) {
+        let limiter = &self.inner.limiter;
+        let mut state = limiter.lock();
+        if let Some(entry) = state
+            .window
+            .iter_mut()
+            .find(|entry| entry.id == self.inner.id)
+        {
+            entry.tokens = u32::try_from(usage.total_tokens).unwrap_or(u32::MAX);
+            limiter.wake_all(&mut state);
+        }
+    }
+
+    /// Calls `record_usage` with the usage of a JSON response body, if it has one.
+    pub(crate) fn record_body_usage(&self, body: &[u8]) {
+        #[derive(Deserialize)]
+        struct WithUsage {
+            usage: Option<Usage>,
+        }
+
+        if let Ok(WithUsage { usage: Some(usage) }) = serde_json::from_slice(body) {
+            self.record_usage(&usage);
+        }
+    }
+}
+
+impl Drop for PermitInner {
+    fn drop(&mut self) {
+        let mut state = self.limiter.lock();
+        state.in_flight -= 1;
+        self.limiter.wake_all(&mut state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "is_sync")]
+    use std::thread;
+
+    #[cfg(feature = "is_sync")]
+    fn usage(total_tokens: u64) -> Usage {
+        serde_json::from_value(serde_json::json!({
+            "completion_tokens": 0,
+            "prompt_tokens": total_tokens,
+            "prompt_cache_hit_tokens": 0,
+            "prompt_cache_miss_tokens": total_tokens,
+            "total_tokens": total_tokens
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(b""), 0);
+        assert_eq!(estimate_tokens(b"12345"), 2);
+    }
+
+    #[cfg(feature = "is_sync")]
+    #[test]
+    fn test_max_in_flight_blocks_until_release() {
+        let limiter = Arc::new(RateLimiter::new(RateLimit::default().with_max_in_flight(1)));
+        let permit = limiter.acquire_blocking(1);
+
+        let waiter = {
+            let limiter = Arc::clone(&limiter);
+            thread::spawn(move || {
+                let start = Instant::now();
+                drop(limiter.acquire_blocking(1));
+                start.elapsed()
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        drop(permit);
+
+        assert!(waiter.join().unwrap() >= Duration::from_millis(40));
+        assert_eq!(limiter.lock().in_flight, 0);
+    }
+
+    #[cfg(feature = "is_sync")]
+    #[test]
+    fn test_requests_and_tokens_per_window() {
+        let window = Duration::from_millis(100);
+        let limiter = Arc::new(RateLimiter::new(
+            RateLimit::default()
+                .with_requests_per_minute(2)
+                .with_window(window),
+        ));
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire_blocking(1);
+        }
+        assert!(start.elapsed() >= window);
+
+        let limiter = Arc::new(RateLimiter::new(
+            RateLimit::default()
+                .with_tokens_per_minute(100)
+                .with_window(window),
+        ));
+        let start = Instant::now();
+        // Larger than the budget, but alone in the window.
+        let permit = limiter.acquire_blocking(500);
+        // Corrected to the actual usage, which leaves room for the next request.
+        permit.record_usage(&usage(40));
+        limiter.acquire_blocking(60);
+        assert!(start.elapsed() < window);
+        limiter.acquire_blocking(1);
+        assert!(start.elapsed() >= window);
+    }
+
+    #[cfg(feature = "is_async")]
+    #[tokio::test]
+    async fn test_async_queue_is_fair() {
+        let limiter = Arc::new(RateLimiter::new(RateLimit::default().with_max_in_flight(1)));
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let first = limiter.acquire(1).await;
+
+        let mut tasks = Vec::new();
+        for i in 0..4 {
+            let limiter = Arc::clone(&limiter);
+            let order = Arc::clone(&order);
+            tasks.push(tokio::spawn(async move {
+                let _permit = limiter.acquire(1).await;
+                order.lock().unwrap().push(i);
+                tokio::task::yield_now().await;
+            }));
+            // Lets the task join the queue before the next one.
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        drop(first);
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3]);
+    }
+
+    #[cfg(feature = "is_async")]
+    #[tokio::test]
+    async fn test_async_cancelled_waiter_leaves_queue() {
+        let limiter = Arc::new(RateLimiter::new(RateLimit::default().with_max_in_flight(1)));
+        let first = limiter.acquire(1).await;
+
+        let cancelled = tokio::time::timeout(Duration::from_millis(10), limiter.acquire(1)).await;
+        assert!(cancelled.is_err());
+        drop(first);
+
+        tokio::time::timeout(Duration::from_secs(1), limiter.acquire(1))
+            .await
+            .unwrap();
+    }
+}
@@ -1,7 +1,14 @@
+use crate::error::ValidationError;
 use crate::response::AssistantMessage;
-use anyhow::{anyhow, Ok, Result};
+use schemars::gen::SchemaSettings;
 use schemars::schema::SchemaObject;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[cfg(feature = "derive")]
+pub use deepseek_api_macros::Tool;
 
 /// Represents a frequency penalty with a value between -2 and 2.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -17,10 +24,12 @@ impl FrequencyPenalty {
     /// # Errors
     ///
     /// Returns an error if the value is not between -2 and 2.
-    pub fn new(v: f32) -> Result<Self> {
+    pub fn new(v: f32) -> Result<Self, ValidationError> {
         if !(-2.0..=2.0).contains(&v) {
-            return Err(anyhow!(
-                "Frequency penalty value must be between -2 and 2.".to_string()
+            return Err(ValidationError::new(
+                "frequency_penalty",
+                "between -2 and 2",
+                v,
             ));
         }
         Ok(FrequencyPenalty(v))
@@ -48,10 +57,12 @@ impl PresencePenalty {
     /// # Errors
     ///
     /// Returns an error if the value is not between -2 and 2.
-    pub fn new(v: f32) -> Result<Self> {
+    pub fn new(v: f32) -> Result<Self, ValidationError> {
         if !(-2.0..=2.0).contains(&v) {
-            return Err(anyhow!(
-                "Presence penalty value must be between -2 and 2.".to_string()
+            return Err(ValidationError::new(
+                "presence_penalty",
+                "between -2 and 2",
+                v,
             ));
         }
         Ok(PresencePenalty(v))
@@ -72,6 +83,9 @@ pub enum ResponseType {
     Json,
     #[serde(rename = "text")]
     Text,
+    /// A response type not known to this crate.
+    #[serde(untagged)]
+    Unknown(String),
 }
 
 /// Represents the format of the response.
@@ -92,7 +106,10 @@ impl ResponseFormat {
     }
 }
 
-/// Represents the maximum number of tokens with a value between 1 and 8192.
+/// Represents the maximum number of tokens with a value of at least 1.
+///
+/// The upper bound depends on the model; it is checked against its `ModelCapabilities` by
+/// `RequestBuilder::validate` before the request is sent.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MaxToken(pub u32);
 
@@ -105,10 +122,10 @@ impl MaxToken {
     ///
     /// # Errors
     ///
-    /// Returns an error if the value is not between 1 and 8192.
-    pub fn new(v: u32) -> Result<Self> {
-        if !(1..=8192).contains(&v) {
-            return Err(anyhow!("Max token must be between 1 and 8192.".to_string()));
+    /// Returns an error if the value is 0.
+    pub fn new(v: u32) -> Result<Self, ValidationError> {
+        if v == 0 {
+            return Err(ValidationError::new("max_tokens", "at least 1", v));
         }
         Ok(MaxToken(v))
     }
@@ -122,7 +139,10 @@ impl Default for MaxToken {
 }
 
 /// Represents the stopping criteria for the completion.
+///
+/// Serialized as a string or an array of strings.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum Stop {
     Single(String),
     Multiple(Vec<String>),
@@ -159,9 +179,9 @@ impl Temperature {
     /// # Errors
     ///
     /// Returns an error if the value is not between 0 and 2.
-    pub fn new(v: f32) -> Result<Self> {
+    pub fn new(v: f32) -> Result<Self, ValidationError> {
         if !(0.0..=2.0).contains(&v) {
-            return Err(anyhow!("Temperature must be between 0 and 2.".to_string()));
+            return Err(ValidationError::new("temperature", "between 0 and 2", v));
         }
         Ok(Temperature(v))
     }
@@ -188,9 +208,9 @@ impl TopP {
     /// # Errors
     ///
     /// Returns an error if the value is not between 0.0 and 1.0.
-    pub fn new(v: f32) -> Result<Self> {
+    pub fn new(v: f32) -> Result<Self, ValidationError> {
         if !(0.0..=1.0).contains(&v) {
-            return Err(anyhow!("TopP value must be between 0and 2.".to_string()));
+            return Err(ValidationError::new("top_p", "between 0 and 1", v));
         }
         Ok(TopP(v))
     }
@@ -208,6 +228,9 @@ impl Default for TopP {
 pub enum ToolType {
     #[serde(rename = "function")]
     Function,
+    /// A tool type not known to this crate.
+    #[serde(untagged)]
+    Unknown(String),
 }
 
 /// Represents a function with a description, name, and parameters.
@@ -226,6 +249,83 @@ pub struct ToolObject {
     pub function: Function,
 }
 
+impl ToolObject {
+    /// Creates a function `ToolObject` whose parameters are the JSON Schema of `T`.
+    ///
+    /// Sub-schemas are inlined rather than referenced, and the doc comments of `T` and of its
+    /// fields become the descriptions of the schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the function.
+    /// * `description` - What the function does, read by the model to decide when to call it.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// #[derive(Deserialize, JsonSchema)]
+    /// struct GetWeather {
+    ///     /// The location to get the weather for.
+    ///     location: String,
+    /// }
+    ///
+    /// let tool = ToolObject::from_type::<GetWeather>("get_weather", "Get weather of a location");
+    /// ```
+    pub fn from_type<T: JsonSchema>(name: &str, description: &str) -> Self {
+        ToolObject {
+            tool_type: ToolType::Function,
+            function: Function {
+                description: description.to_string(),
+                name: name.to_string(),
+                parameters: schema_for::<T>(),
+            },
+        }
+    }
+}
+
+/// Generates the JSON Schema of `T` with its sub-schemas inlined and without a title.
+pub(crate) fn schema_for<T: JsonSchema>() -> SchemaObject {
+    let generator = SchemaSettings::draft07()
+        .with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        })
+        .into_generator();
+    let mut schema = generator.into_root_schema_for::<T>().schema;
+    schema.metadata().title = None;
+    schema
+}
+
+/// A type describing the arguments of a function tool.
+///
+/// Implement it, or derive it with `#[derive(Tool)]` when the `derive` feature is enabled,
+/// to get the `ToolObject` of a tool from its arguments type and to decode its calls with
+/// `ToolCall::parse_arguments`.
+///
+/// # Examples
+///
+/// ```ignore
+/// /// Get the weather of a location.
+/// #[derive(Deserialize, JsonSchema, Tool)]
+/// struct GetWeather {
+///     /// The location to get the weather for.
+///     location: String,
+/// }
+///
+/// let tools = vec![GetWeather::tool_object()];
+/// ```
+pub trait Tool: JsonSchema + DeserializeOwned {
+    /// The name of the function.
+    const NAME: &'static str;
+    /// What the function does.
+    const DESCRIPTION: &'static str;
+
+    /// Returns the `ToolObject` describing this tool.
+    fn tool_object() -> ToolObject {
+        ToolObject::from_type::<Self>(Self::NAME, Self::DESCRIPTION)
+    }
+}
+
 /// Represents the choice of chat completion tool.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ChatCompletionToolChoice {
@@ -252,12 +352,43 @@ pub struct ChatCompletionNamedToolChoice {
 }
 
 /// Represents the choice of tool.
+///
+/// Serialized as `"none"`, `"auto"` or `"required"`, or as
+/// `{"type": "function", "function": {"name": ...}}` for a named function.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum ToolChoice {
     ChatCompletion(ChatCompletionToolChoice),
     ChatCompletionNamed(ChatCompletionNamedToolChoice),
 }
 
+impl ToolChoice {
+    /// The model does not call any tool.
+    pub fn none() -> Self {
+        ToolChoice::ChatCompletion(ChatCompletionToolChoice::None)
+    }
+
+    /// The model chooses whether to call tools.
+    pub fn auto() -> Self {
+        ToolChoice::ChatCompletion(ChatCompletionToolChoice::Auto)
+    }
+
+    /// The model calls at least one tool.
+    pub fn required() -> Self {
+        ToolChoice::ChatCompletion(ChatCompletionToolChoice::Required)
+    }
+
+    /// The model calls the function `name`.
+    pub fn function(name: &str) -> Self {
+        ToolChoice::ChatCompletionNamed(ChatCompletionNamedToolChoice {
+            tool_type: ToolType::Function,
+            function: FunctionChoice {
+                name: name.to_string(),
+            },
+        })
+    }
+}
+
 /// Represents the top log probabilities with a value between 0 and 20.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TopLogprobs(pub u32);
@@ -272,11 +403,9 @@ impl TopLogprobs {
     /// # Errors
     ///
     /// Returns an error if the value is not between 0 and 20.
-    pub fn new(v: u32) -> Result<Self> {
+    pub fn new(v: u32) -> Result<Self, ValidationError> {
         if v > 20 {
-            return Err(anyhow!(
-                "Top log probs must be between 0 and 20.".to_string()
-            ));
+            return Err(ValidationError::new("top_logprobs", "between 0 and 20", v));
         }
         Ok(TopLogprobs(v))
     }
@@ -289,6 +418,62 @@ impl Default for TopLogprobs {
     }
 }
 
+/// Represents the number of choices to generate with a value between 1 and 128.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChoiceCount(pub u32);
+
+impl ChoiceCount {
+    /// Creates a new `ChoiceCount` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - An unsigned integer representing the number of choices.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value is not between 1 and 128.
+    pub fn new(v: u32) -> Result<Self, ValidationError> {
+        if !(1..=128).contains(&v) {
+            return Err(ValidationError::new("n", "between 1 and 128", v));
+        }
+        Ok(ChoiceCount(v))
+    }
+}
+
+impl Default for ChoiceCount {
+    /// Returns the default value for `ChoiceCount`, which is 1.
+    fn default() -> Self {
+        ChoiceCount(1)
+    }
+}
+
+/// Represents biases added to the logits of tokens, keyed by token id, with values between
+/// -100 and 100.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LogitBias(pub BTreeMap<u32, i32>);
+
+impl LogitBias {
+    /// Creates a new `LogitBias` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - The bias of each token, keyed by token id.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a bias is not between -100 and 100.
+    pub fn new(v: BTreeMap<u32, i32>) -> Result<Self, ValidationError> {
+        if let Some(bias) = v.values().find(|bias| !(-100..=100).contains(*bias)) {
+            return Err(ValidationError::new(
+                "logit_bias",
+                "between -100 and 100",
+                bias,
+            ));
+        }
+        Ok(LogitBias(v))
+    }
+}
+
 /// Represents a message request with different roles.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "role")]
@@ -427,3 +612,98 @@ impl ToolMessageRequest {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_choice_and_stop_wire_format() {
+        assert_eq!(
+            serde_json::to_value(ToolChoice::auto()).unwrap(),
+            json!("auto")
+        );
+        assert_eq!(
+            serde_json::to_value(ToolChoice::function("get_weather")).unwrap(),
+            json!({"type": "function", "function": {"name": "get_weather"}})
+        );
+        assert_eq!(
+            serde_json::from_value::<ToolChoice>(json!("required")).unwrap(),
+            ToolChoice::required()
+        );
+
+        assert_eq!(
+            serde_json::to_value(Stop::Single("\n".to_string())).unwrap(),
+            json!("\n")
+        );
+        assert_eq!(
+            serde_json::from_value::<Stop>(json!(["a", "b"])).unwrap(),
+            Stop::Multiple(vec!["a".to_string(), "b".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_choice_count_and_logit_bias() {
+        assert!(ChoiceCount::new(0).is_err());
+        assert_eq!(ChoiceCount::new(4).unwrap(), ChoiceCount(4));
+
+        let bias = LogitBias::new(BTreeMap::from([(42, -100), (7, 5)])).unwrap();
+        assert_eq!(
+            serde_json::to_value(&bias).unwrap(),
+            json!({"7": 5, "42": -100})
+        );
+        let err = LogitBias::new(BTreeMap::from([(42, 101)])).unwrap_err();
+        assert_eq!(err.parameter, "logit_bias");
+    }
+
+    /// Arguments of the weather tool.
+    #[derive(Deserialize, JsonSchema)]
+    #[allow(dead_code)]
+    struct GetWeather {
+        /// The location to get the weather for.
+        location: String,
+        unit: Option<Unit>,
+    }
+
+    #[derive(Deserialize, JsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    #[allow(dead_code)]
+    enum Unit {
+        Celsius,
+        Fahrenheit,
+    }
+
+    impl Tool for GetWeather {
+        const NAME: &'static str = "get_weather";
+        const DESCRIPTION: &'static str = "Get weather of a location";
+    }
+
+    #[test]
+    fn test_tool_object_from_type() {
+        let tool = GetWeather::tool_object();
+        assert_eq!(tool.tool_type, ToolType::Function);
+        assert_eq!(tool.function.name, "get_weather");
+        assert_eq!(tool.function.description, "Get weather of a location");
+
+        let parameters = serde_json::to_value(&tool.function.parameters).unwrap();
+        assert_eq!(
+            parameters,
+            json!({
+                "description": "Arguments of the weather tool.",
+                "type": "object",
+                "required": ["location"],
+                "properties": {
+                    "location": {
+                        "description": "The location to get the weather for.",
+                        "type": "string"
+                    },
+                    "unit": {
+                        "type": ["string", "null"],
+                        "enum": ["celsius", "fahrenheit"]
+                    }
+                }
+            })
+        );
+    }
+}
@@ -1,17 +1,42 @@
-use serde::{de::DeserializeOwned, ser::SerializeStruct, Serialize, Serializer};
+use http::HeaderMap;
+use serde::{
+    de::DeserializeOwned,
+    ser::{SerializeSeq, SerializeStruct},
+    Serialize, Serializer,
+};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 
 use crate::{
     request::{
-        FrequencyPenalty, MaxToken, MessageRequest, PresencePenalty, ResponseFormat, ResponseType,
-        Stop, StreamOptions, Temperature, ToolChoice, ToolObject, TopLogprobs, TopP,
+        ChoiceCount, FrequencyPenalty, LogitBias, MaxToken, MessageRequest, PresencePenalty,
+        ResponseFormat, ResponseType, Stop, StreamOptions, Temperature, ToolChoice, ToolObject,
+        TopLogprobs, TopP,
     },
     response::{
-        ChatCompletion, ChatCompletionStream, ChatResponse, JSONChoiceStream, ModelType,
+        AssistantMessage, ChatCompletion, ChatCompletionStream, JSONChoiceStream, ModelType,
         TextChoiceStream,
     },
-    DeepSeekClient,
+    Tokenizer, ValidationError, ValidationErrors,
 };
-use anyhow::{Ok, Result};
+
+/// A client able to send the requests built by a `RequestBuilder`.
+///
+/// Implemented by both `deepseek_api::r#async::DeepSeekClient`, whose `Output` is a future,
+/// and `deepseek_api::blocking::DeepSeekClient`, whose `Output` is the response itself.
+pub trait CompletionClient {
+    /// The value returned when sending a request built by `B`.
+    type Output<'a, B: RequestBuilder + 'a>
+    where
+        Self: 'a;
+
+    /// Sends the request built by `request_builder`.
+    fn send_request<'a, B: RequestBuilder + 'a>(
+        &'a self,
+        request_builder: B,
+    ) -> Self::Output<'a, B>;
+}
 
 pub trait RequestBuilder: Sized + Send {
     type Request: Serialize + Send;
@@ -22,23 +47,141 @@ pub trait RequestBuilder: Sized + Send {
     fn is_stream(&self) -> bool;
     fn build(self) -> Self::Request;
 
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "is_sync")] {
-            fn do_request(self, client: &DeepSeekClient) ->  Result<ChatResponse<Self::Response, Self::Item>>  {
-                client.send_completion_request(self)
+    /// Returns the extra body fields and headers of the request, if any.
+    fn extras(&self) -> Option<&RequestExtras> {
+        None
+    }
+
+    /// Checks the combination of parameters against the capabilities of the chosen model.
+    ///
+    /// The clients run it before sending the request, so a request the API would reject is
+    /// not sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns every problem found, not only the first one.
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        Ok(())
+    }
+
+    /// Sends the request with either the async or the blocking client.
+    ///
+    /// With the async client the result must be awaited.
+    fn do_request<'a, C: CompletionClient>(self, client: &'a C) -> C::Output<'a, Self>
+    where
+        Self: 'a,
+    {
+        client.send_request(self)
+    }
+}
+
+/// Additions to the body and headers of a single request, set with the `extra_body` and
+/// `extra_headers` methods of the request builders.
+///
+/// They give access to parameters and headers this crate does not know about yet.
+#[derive(Debug, Clone, Default)]
+pub struct RequestExtras {
+    body: Map<String, Value>,
+    override_body: bool,
+    headers: HeaderMap,
+}
+
+impl RequestExtras {
+    /// Returns the fields merged into the JSON body of the request.
+    pub fn body(&self) -> &Map<String, Value> {
+        &self.body
+    }
+
+    /// Returns the headers sent on top of the client's headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    fn add_body(&mut self, value: Value) -> Result<(), ValidationError> {
+        match value {
+            Value::Object(fields) => {
+                self.body.extend(fields);
+                Ok(())
             }
-        } else {
-            fn do_request(self, client: &DeepSeekClient) ->  impl std::future::Future<Output = Result<ChatResponse<Self::Response, Self::Item>>> + Send {async {
-                client.send_completion_request(self).await
-            }}
+            value => Err(ValidationError::new("extra_body", "a JSON object", value)),
+        }
+    }
+
+    /// Serializes `request` and merges the extra body fields into it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Validation` if an extra field is already set by the request and
+    /// overriding was not enabled.
+    #[cfg_attr(not(any(feature = "is_async", feature = "is_sync")), allow(dead_code))]
+    pub(crate) fn encode<T: Serialize>(&self, request: &T) -> crate::Result<Vec<u8>> {
+        if self.body.is_empty() {
+            return Ok(serde_json::to_vec(request)?);
+        }
+
+        let mut body = match serde_json::to_value(request)? {
+            Value::Object(body) => body,
+            _ => unreachable!("requests serialize to JSON objects"),
+        };
+        for (key, value) in &self.body {
+            if !self.override_body && body.contains_key(key) {
+                return Err(ValidationError::new(
+                    "extra_body",
+                    "fields not already set by the request",
+                    key,
+                )
+                .into());
+            }
+            body.insert(key.clone(), value.clone());
         }
+        Ok(serde_json::to_vec(&body)?)
+    }
+}
+
+/// A completion request checked and encoded by its `RequestBuilder`, ready to be sent.
+#[cfg_attr(not(any(feature = "is_async", feature = "is_sync")), allow(dead_code))]
+#[derive(Debug, Clone)]
+pub(crate) struct PreparedRequest {
+    pub(crate) path: &'static str,
+    pub(crate) body: Vec<u8>,
+    pub(crate) headers: HeaderMap,
+    pub(crate) is_stream: bool,
+}
+
+#[cfg_attr(not(any(feature = "is_async", feature = "is_sync")), allow(dead_code))]
+impl PreparedRequest {
+    /// Validates and encodes the request built by `request_builder`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidRequest` if the request is invalid for its model, and
+    /// `Error::Validation` if its extra body fields cannot be merged into it.
+    pub(crate) fn new<B: RequestBuilder>(request_builder: B) -> crate::Result<Self> {
+        let path = if request_builder.is_beta() {
+            "/beta/completions"
+        } else {
+            "/chat/completions"
+        };
+        let is_stream = request_builder.is_stream();
+
+        request_builder.validate()?;
+        let extras = request_builder.extras().cloned().unwrap_or_default();
+
+        let request = request_builder.build();
+        Ok(PreparedRequest {
+            path,
+            body: extras.encode(&request)?,
+            headers: extras.headers,
+            is_stream,
+        })
     }
 }
 
 /// Represents a request for completions.
 #[derive(Debug, Default, Clone)]
 pub struct CompletionsRequest<'a> {
-    pub messages: &'a [MessageRequest],
+    /// The messages to send, owned when `fit_to_context` dropped some of them.
+    pub messages: Cow<'a, [MessageRequest]>,
     pub model: ModelType,
     pub max_tokens: Option<MaxToken>,
     pub response_format: Option<ResponseFormat>,
@@ -47,6 +190,7 @@ pub struct CompletionsRequest<'a> {
     pub stream_options: Option<StreamOptions>,
     pub tools: Option<&'a [ToolObject]>,
     pub tool_choice: Option<ToolChoice>,
+    pub user: Option<String>,
 
     // ignore when model is deepseek-reasoner
     pub temperature: Option<Temperature>,
@@ -55,6 +199,12 @@ pub struct CompletionsRequest<'a> {
     pub frequency_penalty: Option<FrequencyPenalty>,
     pub logprobs: Option<bool>,
     pub top_logprobs: Option<TopLogprobs>,
+    pub n: Option<ChoiceCount>,
+    pub seed: Option<i64>,
+    pub logit_bias: Option<LogitBias>,
+
+    /// Sends the `reasoning_content` of prior assistant messages instead of dropping it.
+    pub keep_reasoning_content: bool,
 }
 
 impl Serialize for CompletionsRequest<'_> {
@@ -62,9 +212,13 @@ impl Serialize for CompletionsRequest<'_> {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("CompletionsRequest", 12)?;
+        let mut state = serializer.serialize_struct("CompletionsRequest", 20)?;
 
-        state.serialize_field("messages", &self.messages)?;
+        if self.keep_reasoning_content {
+            state.serialize_field("messages", &self.messages)?;
+        } else {
+            state.serialize_field("messages", &WithoutReasoning(&self.messages))?;
+        }
         state.serialize_field("model", &self.model)?;
 
         if let Some(max_tokens) = &self.max_tokens {
@@ -86,9 +240,12 @@ impl Serialize for CompletionsRequest<'_> {
         if let Some(tool_choice) = &self.tool_choice {
             state.serialize_field("tool_choice", tool_choice)?;
         }
+        if let Some(user) = &self.user {
+            state.serialize_field("user", user)?;
+        }
 
-        // Skip these fields if model is DeepSeekReasoner
-        if self.model != ModelType::DeepSeekReasoner {
+        // Skip these fields if model is a reasoner like DeepSeekReasoner
+        if !self.model.is_reasoner() {
             if let Some(temperature) = &self.temperature {
                 state.serialize_field("temperature", temperature)?;
             }
@@ -107,49 +264,102 @@ impl Serialize for CompletionsRequest<'_> {
             if let Some(top_logprobs) = &self.top_logprobs {
                 state.serialize_field("top_logprobs", top_logprobs)?;
             }
+            if let Some(n) = &self.n {
+                state.serialize_field("n", n)?;
+            }
+            if let Some(seed) = &self.seed {
+                state.serialize_field("seed", seed)?;
+            }
+            if let Some(logit_bias) = &self.logit_bias {
+                state.serialize_field("logit_bias", logit_bias)?;
+            }
         }
 
         state.end()
     }
 }
 
+/// Serializes messages without the `reasoning_content` of assistant messages, which the API
+/// rejects.
+///
+/// A trailing assistant message with `prefix` set keeps it, as it is the start of the reply
+/// to complete rather than a prior turn.
+struct WithoutReasoning<'a>(&'a [MessageRequest]);
+
+impl Serialize for WithoutReasoning<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for (i, message) in self.0.iter().enumerate() {
+            match message {
+                MessageRequest::Assistant(msg)
+                    if msg.reasoning_content.is_some()
+                        && !(msg.prefix && i + 1 == self.0.len()) =>
+                {
+                    seq.serialize_element(&MessageRequest::Assistant(AssistantMessage {
+                        reasoning_content: None,
+                        ..msg.clone()
+                    }))?;
+                }
+                message => seq.serialize_element(message)?,
+            }
+        }
+        seq.end()
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct CompletionsRequestBuilder<'a> {
-    //todo too many colone when use this type, improve it especially for message field
-    beta: bool,
-    messages: &'a [MessageRequest],
-    model: ModelType,
+    pub(crate) beta: bool,
+    pub(crate) messages: Cow<'a, [MessageRequest]>,
+    pub(crate) model: ModelType,
 
-    stream: bool,
-    stream_options: Option<StreamOptions>,
+    pub(crate) stream: bool,
+    pub(crate) stream_options: Option<StreamOptions>,
 
-    max_tokens: Option<MaxToken>,
-    response_format: Option<ResponseFormat>,
-    stop: Option<Stop>,
-    tools: Option<&'a [ToolObject]>,
-    tool_choice: Option<ToolChoice>,
-    temperature: Option<Temperature>,
-    top_p: Option<TopP>,
-    presence_penalty: Option<PresencePenalty>,
-    frequency_penalty: Option<FrequencyPenalty>,
-    logprobs: Option<bool>,
-    top_logprobs: Option<TopLogprobs>,
+    pub(crate) max_tokens: Option<MaxToken>,
+    pub(crate) response_format: Option<ResponseFormat>,
+    pub(crate) stop: Option<Stop>,
+    pub(crate) tools: Option<&'a [ToolObject]>,
+    pub(crate) tool_choice: Option<ToolChoice>,
+    pub(crate) temperature: Option<Temperature>,
+    pub(crate) top_p: Option<TopP>,
+    pub(crate) presence_penalty: Option<PresencePenalty>,
+    pub(crate) frequency_penalty: Option<FrequencyPenalty>,
+    pub(crate) logprobs: Option<bool>,
+    pub(crate) top_logprobs: Option<TopLogprobs>,
+    pub(crate) n: Option<ChoiceCount>,
+    pub(crate) seed: Option<i64>,
+    pub(crate) user: Option<String>,
+    pub(crate) logit_bias: Option<LogitBias>,
+    pub(crate) keep_reasoning_content: bool,
+    pub(crate) strict: bool,
+    pub(crate) extras: RequestExtras,
 }
 
 impl<'a> CompletionsRequestBuilder<'a> {
     pub fn new(messages: &'a [MessageRequest]) -> Self {
         Self {
-            messages,
+            messages: Cow::Borrowed(messages),
             model: ModelType::DeepSeekChat,
             ..Default::default()
         }
     }
-    pub fn use_model(mut self, model: ModelType) -> Self {
-        self.model = model;
+    /// Sets the model, either a `ModelType` shortcut or any model id.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let builder = CompletionsRequestBuilder::new(&messages).use_model("deepseek-v9");
+    /// ```
+    pub fn use_model(mut self, model: impl Into<ModelType>) -> Self {
+        self.model = model.into();
         self
     }
 
-    pub fn max_tokens(mut self, value: u32) -> Result<Self> {
+    pub fn max_tokens(mut self, value: u32) -> Result<Self, ValidationError> {
         self.max_tokens = Some(MaxToken::new(value)?);
         Ok(self)
     }
@@ -189,22 +399,22 @@ impl<'a> CompletionsRequestBuilder<'a> {
         self
     }
 
-    pub fn temperature(mut self, value: f32) -> Result<Self> {
+    pub fn temperature(mut self, value: f32) -> Result<Self, ValidationError> {
         self.temperature = Some(Temperature::new(value)?);
         Ok(self)
     }
 
-    pub fn top_p(mut self, value: f32) -> Result<Self> {
+    pub fn top_p(mut self, value: f32) -> Result<Self, ValidationError> {
         self.top_p = Some(TopP::new(value)?);
         Ok(self)
     }
 
-    pub fn presence_penalty(mut self, value: f32) -> Result<Self> {
+    pub fn presence_penalty(mut self, value: f32) -> Result<Self, ValidationError> {
         self.presence_penalty = Some(PresencePenalty::new(value)?);
         Ok(self)
     }
 
-    pub fn frequency_penalty(mut self, value: f32) -> Result<Self> {
+    pub fn frequency_penalty(mut self, value: f32) -> Result<Self, ValidationError> {
         self.frequency_penalty = Some(FrequencyPenalty::new(value)?);
         Ok(self)
     }
@@ -214,10 +424,178 @@ impl<'a> CompletionsRequestBuilder<'a> {
         self
     }
 
-    pub fn top_logprobs(mut self, value: u32) -> Result<Self> {
+    pub fn top_logprobs(mut self, value: u32) -> Result<Self, ValidationError> {
         self.top_logprobs = Some(TopLogprobs::new(value)?);
         Ok(self)
     }
+
+    /// Sets the number of choices to generate, between 1 and 128.
+    pub fn n(mut self, value: u32) -> Result<Self, ValidationError> {
+        self.n = Some(ChoiceCount::new(value)?);
+        Ok(self)
+    }
+
+    /// Sets the seed used for sampling, so that repeated requests return the same result
+    /// as far as the server supports it.
+    pub fn seed(mut self, value: i64) -> Self {
+        self.seed = Some(value);
+        self
+    }
+
+    /// Sets an identifier of the end user, to help the server detect abuse.
+    pub fn user(mut self, value: &str) -> Self {
+        self.user = Some(value.to_string());
+        self
+    }
+
+    /// Sets biases added to the logits of tokens, keyed by token id, between -100 and 100.
+    pub fn logit_bias(mut self, value: BTreeMap<u32, i32>) -> Result<Self, ValidationError> {
+        self.logit_bias = Some(LogitBias::new(value)?);
+        Ok(self)
+    }
+
+    /// Sends the `reasoning_content` of prior assistant messages.
+    ///
+    /// By default it is dropped from the request, because the DeepSeek API rejects
+    /// assistant messages carrying it; the messages themselves are left untouched. Enable
+    /// this for servers that accept it.
+    pub fn keep_reasoning_content(mut self, value: bool) -> Self {
+        self.keep_reasoning_content = value;
+        self
+    }
+
+    /// Rejects the parameters a reasoning model ignores, such as `temperature`, in
+    /// `validate` instead of silently leaving them out of the request.
+    pub fn strict(mut self, value: bool) -> Self {
+        self.strict = value;
+        self
+    }
+
+    /// Adds fields to the JSON body of the request, e.g. a parameter this crate does not
+    /// support yet. Several calls add up.
+    ///
+    /// A field the request already sets is rejected with `Error::Validation` when the request
+    /// is sent, unless `extra_body_override(true)` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `value` is not a JSON object.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let builder = CompletionsRequestBuilder::new(&messages)
+    ///     .extra_body(json!({"thinking": {"type": "enabled"}}))?;
+    /// ```
+    pub fn extra_body(mut self, value: Value) -> Result<Self, ValidationError> {
+        self.extras.add_body(value)?;
+        Ok(self)
+    }
+
+    /// Lets the fields of `extra_body` replace the fields set by the request.
+    pub fn extra_body_override(mut self, value: bool) -> Self {
+        self.extras.override_body = value;
+        self
+    }
+
+    /// Adds headers to this request, on top of the client's default headers.
+    pub fn extra_headers(mut self, headers: HeaderMap) -> Self {
+        self.extras.headers.extend(headers);
+        self
+    }
+
+    /// Drops the oldest turns of the conversation until the prompt fits in the context
+    /// length of the model, counting tokens with `Tokenizer::default()`.
+    ///
+    /// See `fit_to_context_with`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prompt does not fit even with only its last turn.
+    pub fn fit_to_context(self) -> Result<Self, ValidationError> {
+        self.fit_to_context_with(&Tokenizer::default())
+    }
+
+    /// Drops the oldest turns of the conversation until the prompt fits in the context
+    /// length of the model, counting tokens with `tokenizer`.
+    ///
+    /// The prompt must leave room for `max_tokens`, or for the largest reply of the model
+    /// when `max_tokens` is unset. A turn is a user message with the messages following it
+    /// up to the next user message, so tool calls are dropped along with their results.
+    /// System messages and the last turn are always kept; the messages passed to `new` are
+    /// left untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prompt does not fit even with only its last turn.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let builder = CompletionsRequestBuilder::new(&history)
+    ///     .max_tokens(1024)?
+    ///     .fit_to_context_with(&tokenizer)?;
+    /// ```
+    pub fn fit_to_context_with(mut self, tokenizer: &Tokenizer) -> Result<Self, ValidationError> {
+        let capabilities = self.model.capabilities();
+        let reserved = self
+            .max_tokens
+            .as_ref()
+            .map_or(capabilities.max_output_tokens, |max_tokens| max_tokens.0);
+        let budget = capabilities.context_length.saturating_sub(reserved) as usize;
+
+        let counts: Vec<usize> = self
+            .messages
+            .iter()
+            .map(|message| tokenizer.count_message(message, self.keep_reasoning_content))
+            .collect();
+        let mut total = counts.iter().sum::<usize>()
+            + self.tools.map_or(0, |tools| tokenizer.count_tools(tools));
+
+        let mut turns: Vec<Vec<usize>> = Vec::new();
+        for (i, message) in self.messages.iter().enumerate() {
+            match message {
+                MessageRequest::System(_) => {}
+                MessageRequest::User(_) => turns.push(vec![i]),
+                _ => match turns.last_mut() {
+                    Some(turn) => turn.push(i),
+                    None => turns.push(vec![i]),
+                },
+            }
+        }
+
+        let mut dropped = vec![false; self.messages.len()];
+        let mut turns = turns[..turns.len().saturating_sub(1)].iter();
+        while total > budget {
+            let Some(turn) = turns.next() else {
+                return Err(ValidationError::new(
+                    "messages",
+                    format!(
+                        "at most {} tokens to leave {} for the reply of {}",
+                        budget,
+                        reserved,
+                        self.model.id()
+                    ),
+                    format!("about {} tokens", total),
+                ));
+            };
+            for &i in turn {
+                dropped[i] = true;
+                total -= counts[i];
+            }
+        }
+
+        if dropped.contains(&true) {
+            self.messages = self
+                .messages
+                .iter()
+                .zip(dropped)
+                .filter(|(_, dropped)| !dropped)
+                .map(|(message, _)| message.clone())
+                .collect();
+        }
+        Ok(self)
+    }
 }
 
 impl<'a> RequestBuilder for CompletionsRequestBuilder<'a> {
@@ -233,6 +611,103 @@ impl<'a> RequestBuilder for CompletionsRequestBuilder<'a> {
         self.stream
     }
 
+    fn extras(&self) -> Option<&RequestExtras> {
+        Some(&self.extras)
+    }
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let capabilities = self.model.capabilities();
+        let model = self.model.id();
+        let mut errors = Vec::new();
+
+        if self.messages.is_empty() {
+            errors.push(ValidationError::new("messages", "not empty", "no message"));
+        }
+        if let Some(MessageRequest::Assistant(message)) = self.messages.last() {
+            if message.prefix && !self.beta {
+                errors.push(ValidationError::new(
+                    "messages",
+                    "sent with use_beta(true) to end with a prefix message",
+                    "a prefix message",
+                ));
+            }
+        }
+        if let Some(max_tokens) = &self.max_tokens {
+            if max_tokens.0 > capabilities.max_output_tokens {
+                errors.push(ValidationError::new(
+                    "max_tokens",
+                    format!("at most {} for {}", capabilities.max_output_tokens, model),
+                    max_tokens.0,
+                ));
+            }
+        }
+        if let Some(tools) = self.tools {
+            if !capabilities.supports_tools {
+                errors.push(ValidationError::new(
+                    "tools",
+                    format!("unset for {}, which does not support tools", model),
+                    format!("{} tools", tools.len()),
+                ));
+            }
+        }
+        if let (Some(tool_choice), None) = (&self.tool_choice, self.tools) {
+            errors.push(ValidationError::new(
+                "tool_choice",
+                "unset when no tools are sent",
+                format!("{:?}", tool_choice),
+            ));
+        }
+        if let (Some(top_logprobs), false) = (&self.top_logprobs, self.logprobs == Some(true)) {
+            errors.push(ValidationError::new(
+                "top_logprobs",
+                "unset unless logprobs is true",
+                top_logprobs.0,
+            ));
+        }
+        if let (Some(stream_options), false) = (&self.stream_options, self.stream) {
+            errors.push(ValidationError::new(
+                "stream_options",
+                "unset unless stream is true",
+                format!("{:?}", stream_options),
+            ));
+        }
+
+        if self.strict && capabilities.is_reasoner {
+            let ignored = format!("unset for {}, which ignores it", model);
+            let mut reject = |parameter, value: Option<String>| {
+                if let Some(value) = value {
+                    errors.push(ValidationError::new(parameter, ignored.clone(), value));
+                }
+            };
+            reject(
+                "temperature",
+                self.temperature.as_ref().map(|v| v.0.to_string()),
+            );
+            reject("top_p", self.top_p.as_ref().map(|v| v.0.to_string()));
+            reject(
+                "presence_penalty",
+                self.presence_penalty.as_ref().map(|v| v.0.to_string()),
+            );
+            reject(
+                "frequency_penalty",
+                self.frequency_penalty.as_ref().map(|v| v.0.to_string()),
+            );
+            reject("logprobs", self.logprobs.map(|v| v.to_string()));
+            reject(
+                "top_logprobs",
+                self.top_logprobs.as_ref().map(|v| v.0.to_string()),
+            );
+            reject("n", self.n.as_ref().map(|v| v.0.to_string()));
+            reject("seed", self.seed.map(|v| v.to_string()));
+            reject(
+                "logit_bias",
+                self.logit_bias.as_ref().map(|v| format!("{:?}", v.0)),
+            );
+        }
+
+        ValidationErrors(errors).into_result()
+    }
+
     fn build(self) -> CompletionsRequest<'a> {
         CompletionsRequest {
             messages: self.messages,
@@ -250,6 +725,11 @@ impl<'a> RequestBuilder for CompletionsRequestBuilder<'a> {
             frequency_penalty: self.frequency_penalty,
             logprobs: self.logprobs,
             top_logprobs: self.top_logprobs,
+            n: self.n,
+            seed: self.seed,
+            user: self.user,
+            logit_bias: self.logit_bias,
+            keep_reasoning_content: self.keep_reasoning_content,
         }
     }
 }
@@ -297,6 +777,7 @@ pub struct FMICompletionsRequestBuilder {
     suffix: String,
     temperature: Option<Temperature>,
     top_p: Option<TopP>,
+    extras: RequestExtras,
 }
 
 impl FMICompletionsRequestBuilder {
@@ -312,12 +793,19 @@ impl FMICompletionsRequestBuilder {
         }
     }
 
+    /// Sets the model, which must support FIM completions; `ModelType::DeepSeekChat` by
+    /// default.
+    pub fn use_model(mut self, model: impl Into<ModelType>) -> Self {
+        self.model = model.into();
+        self
+    }
+
     pub fn echo(mut self, value: bool) -> Self {
         self.echo = value;
         self
     }
 
-    pub fn frequency_penalty(mut self, value: f32) -> Result<Self> {
+    pub fn frequency_penalty(mut self, value: f32) -> Result<Self, ValidationError> {
         self.frequency_penalty = Some(FrequencyPenalty::new(value)?);
         Ok(self)
     }
@@ -327,12 +815,12 @@ impl FMICompletionsRequestBuilder {
         self
     }
 
-    pub fn max_tokens(mut self, value: u32) -> Result<Self> {
+    pub fn max_tokens(mut self, value: u32) -> Result<Self, ValidationError> {
         self.max_tokens = Some(MaxToken::new(value)?);
         Ok(self)
     }
 
-    pub fn presence_penalty(mut self, value: f32) -> Result<Self> {
+    pub fn presence_penalty(mut self, value: f32) -> Result<Self, ValidationError> {
         self.presence_penalty = Some(PresencePenalty::new(value)?);
         Ok(self)
     }
@@ -352,15 +840,38 @@ impl FMICompletionsRequestBuilder {
         self
     }
 
-    pub fn temperature(mut self, value: f32) -> Result<Self> {
+    pub fn temperature(mut self, value: f32) -> Result<Self, ValidationError> {
         self.temperature = Some(Temperature::new(value)?);
         Ok(self)
     }
 
-    pub fn top_p(mut self, value: f32) -> Result<Self> {
+    pub fn top_p(mut self, value: f32) -> Result<Self, ValidationError> {
         self.top_p = Some(TopP::new(value)?);
         Ok(self)
     }
+
+    /// Adds fields to the JSON body of the request; see
+    /// `CompletionsRequestBuilder::extra_body`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `value` is not a JSON object.
+    pub fn extra_body(mut self, value: Value) -> Result<Self, ValidationError> {
+        self.extras.add_body(value)?;
+        Ok(self)
+    }
+
+    /// Lets the fields of `extra_body` replace the fields set by the request.
+    pub fn extra_body_override(mut self, value: bool) -> Self {
+        self.extras.override_body = value;
+        self
+    }
+
+    /// Adds headers to this request, on top of the client's default headers.
+    pub fn extra_headers(mut self, headers: HeaderMap) -> Self {
+        self.extras.headers.extend(headers);
+        self
+    }
 }
 
 impl RequestBuilder for FMICompletionsRequestBuilder {
@@ -376,6 +887,45 @@ impl RequestBuilder for FMICompletionsRequestBuilder {
         self.stream
     }
 
+    fn extras(&self) -> Option<&RequestExtras> {
+        Some(&self.extras)
+    }
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let capabilities = self.model.capabilities();
+        let mut errors = Vec::new();
+
+        if !capabilities.supports_fim {
+            errors.push(ValidationError::new(
+                "model",
+                "a model supporting FIM completions",
+                self.model.id(),
+            ));
+        }
+        if let Some(max_tokens) = &self.max_tokens {
+            if max_tokens.0 > capabilities.max_output_tokens {
+                errors.push(ValidationError::new(
+                    "max_tokens",
+                    format!(
+                        "at most {} for {}",
+                        capabilities.max_output_tokens,
+                        self.model.id()
+                    ),
+                    max_tokens.0,
+                ));
+            }
+        }
+        if let (Some(stream_options), false) = (&self.stream_options, self.stream) {
+            errors.push(ValidationError::new(
+                "stream_options",
+                "unset unless stream is true",
+                format!("{:?}", stream_options),
+            ));
+        }
+
+        ValidationErrors(errors).into_result()
+    }
+
     fn build(self) -> FMICompletionsRequest {
         FMICompletionsRequest {
             model: self.model,
@@ -394,3 +944,242 @@ impl RequestBuilder for FMICompletionsRequestBuilder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages() -> Vec<MessageRequest> {
+        let mut reply = AssistantMessage::new("4");
+        reply.reasoning_content = Some("2 + 2 = 4".to_string());
+        let mut prefix = AssistantMessage::new("").set_prefix("The answer");
+        prefix.reasoning_content = Some("Think again".to_string());
+        vec![
+            MessageRequest::user("2 + 2?"),
+            MessageRequest::Assistant(reply),
+            MessageRequest::user("Are you sure?"),
+            MessageRequest::Assistant(prefix),
+        ]
+    }
+
+    #[test]
+    fn test_reasoning_content_is_dropped() {
+        let messages = messages();
+        let request = CompletionsRequestBuilder::new(&messages).build();
+        let body = serde_json::to_value(&request).unwrap();
+
+        assert!(body["messages"][1].get("reasoning_content").is_none());
+        assert_eq!(body["messages"][1]["content"], "4");
+        assert_eq!(body["messages"][3]["reasoning_content"], "Think again");
+        assert!(matches!(
+            &messages[1],
+            MessageRequest::Assistant(msg) if msg.reasoning_content.is_some()
+        ));
+    }
+
+    #[test]
+    fn test_keep_reasoning_content() {
+        let messages = messages();
+        let request = CompletionsRequestBuilder::new(&messages)
+            .keep_reasoning_content(true)
+            .build();
+        let body = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(body["messages"][1]["reasoning_content"], "2 + 2 = 4");
+    }
+
+    #[test]
+    fn test_sampling_parameters_follow_reasoner_rules() {
+        let messages = vec![MessageRequest::user("Hi")];
+        let builder = || {
+            CompletionsRequestBuilder::new(&messages)
+                .n(2)
+                .unwrap()
+                .seed(7)
+                .user("user-1")
+                .logit_bias(BTreeMap::from([(42, -100)]))
+                .unwrap()
+        };
+
+        let body = serde_json::to_value(builder().build()).unwrap();
+        assert_eq!(body["n"], 2);
+        assert_eq!(body["seed"], 7);
+        assert_eq!(body["user"], "user-1");
+        assert_eq!(body["logit_bias"]["42"], -100);
+
+        let request = builder().use_model(ModelType::DeepSeekReasoner).build();
+        let body = serde_json::to_value(request).unwrap();
+        assert!(body.get("n").is_none());
+        assert!(body.get("seed").is_none());
+        assert!(body.get("logit_bias").is_none());
+        assert_eq!(body["user"], "user-1");
+
+        assert!(CompletionsRequestBuilder::new(&messages).n(0).is_err());
+    }
+
+    #[test]
+    fn test_use_model_id() {
+        let messages = vec![MessageRequest::user("Hi")];
+        let request = CompletionsRequestBuilder::new(&messages)
+            .use_model("deepseek-v9")
+            .temperature(0.5)
+            .unwrap()
+            .build();
+        assert_eq!(request.model, ModelType::Unknown("deepseek-v9".to_string()));
+
+        let body = serde_json::to_value(request).unwrap();
+        assert_eq!(body["model"], "deepseek-v9");
+        assert_eq!(body["temperature"], 0.5);
+
+        let request = CompletionsRequestBuilder::new(&messages)
+            .use_model("deepseek-reasoner")
+            .build();
+        assert_eq!(request.model, ModelType::DeepSeekReasoner);
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let messages = vec![MessageRequest::user("Hi")];
+        let tools = vec![ToolObject::from_type::<()>("noop", "Does nothing")];
+        let errors = CompletionsRequestBuilder::new(&messages)
+            .use_model(ModelType::DeepSeekReasoner)
+            .max_tokens(10_000)
+            .unwrap()
+            .tools(&tools)
+            .top_logprobs(5)
+            .unwrap()
+            .temperature(0.5)
+            .unwrap()
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(errors.0.len(), 3);
+        assert!(errors.contains("max_tokens"));
+        assert!(errors.contains("tools"));
+        assert!(errors.contains("top_logprobs"));
+        assert_eq!(
+            errors.0[0].to_string(),
+            "max_tokens must be at most 8192 for deepseek-reasoner, got 10000"
+        );
+
+        let errors = CompletionsRequestBuilder::new(&messages)
+            .use_model(ModelType::DeepSeekReasoner)
+            .temperature(0.5)
+            .unwrap()
+            .seed(1)
+            .strict(true)
+            .validate()
+            .unwrap_err();
+        assert!(errors.contains("temperature"));
+        assert!(errors.contains("seed"));
+
+        assert!(CompletionsRequestBuilder::new(&messages)
+            .temperature(0.5)
+            .unwrap()
+            .strict(true)
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_validate_fim_model() {
+        let errors = FMICompletionsRequestBuilder::new("fn main", "}")
+            .use_model(ModelType::DeepSeekReasoner)
+            .validate()
+            .unwrap_err();
+        assert_eq!(errors.0.len(), 1);
+        assert!(errors.contains("model"));
+
+        assert!(FMICompletionsRequestBuilder::new("fn main", "}")
+            .validate()
+            .is_ok());
+    }
+
+    fn encode<B: RequestBuilder>(builder: B) -> crate::Result<Value> {
+        let extras = builder.extras().cloned().unwrap_or_default();
+        let body = extras.encode(&builder.build())?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    #[test]
+    fn test_extra_body() {
+        let messages = vec![MessageRequest::user("Hi")];
+        let builder = || {
+            CompletionsRequestBuilder::new(&messages)
+                .extra_body(serde_json::json!({"thinking": {"type": "enabled"}}))
+                .unwrap()
+                .extra_body(serde_json::json!({"model": "deepseek-v9"}))
+                .unwrap()
+        };
+
+        let err = encode(builder()).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Validation(ValidationError { ref value, .. }) if value == "model"
+        ));
+
+        let body = encode(builder().extra_body_override(true)).unwrap();
+        assert_eq!(body["thinking"]["type"], "enabled");
+        assert_eq!(body["model"], "deepseek-v9");
+
+        let err = FMICompletionsRequestBuilder::new("fn main", "}")
+            .extra_body(serde_json::json!([1]))
+            .unwrap_err();
+        assert_eq!(err.parameter, "extra_body");
+    }
+
+    #[test]
+    fn test_fit_to_context_drops_oldest_turns() {
+        crate::ModelCapabilities::register(
+            "test-tiny-context",
+            crate::ModelCapabilities {
+                context_length: 100,
+                max_output_tokens: 20,
+                ..Default::default()
+            },
+        );
+        let mut call = AssistantMessage::new("");
+        call.tool_calls = Some(vec![crate::response::ToolCall {
+            id: "call_0".to_string(),
+            tool_type: "function".to_string(),
+            function: crate::response::Function {
+                name: "lookup".to_string(),
+                arguments: "{}".to_string(),
+            },
+        }]);
+        let messages = vec![
+            MessageRequest::System(crate::request::SystemMessageRequest::new("Be brief.")),
+            MessageRequest::user(&"a".repeat(100)),
+            MessageRequest::Assistant(call),
+            MessageRequest::Tool(crate::request::ToolMessageRequest::new(
+                &"b".repeat(100),
+                "call_0",
+            )),
+            MessageRequest::user(&"c".repeat(40)),
+        ];
+
+        let builder = CompletionsRequestBuilder::new(&messages)
+            .use_model("test-tiny-context")
+            .fit_to_context()
+            .unwrap();
+        let contents: Vec<_> = builder.messages.iter().map(|m| m.get_content()).collect();
+        assert_eq!(contents, vec!["Be brief.", &"c".repeat(40)]);
+        assert_eq!(messages.len(), 5);
+
+        let builder = CompletionsRequestBuilder::new(&messages[3..])
+            .use_model("test-tiny-context")
+            .max_tokens(1)
+            .unwrap()
+            .fit_to_context()
+            .unwrap();
+        assert!(matches!(builder.messages, Cow::Borrowed(_)));
+
+        let long = vec![MessageRequest::user(&"d".repeat(400))];
+        let err = CompletionsRequestBuilder::new(&long)
+            .use_model("test-tiny-context")
+            .fit_to_context()
+            .err()
+            .unwrap();
+        assert_eq!(err.parameter, "messages");
+    }
+}
@@ -1,8 +1,9 @@
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use crate::{ModelCapabilities, StreamError, ToolArgumentsError};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
 use std::fmt;
 
-use crate::json_stream::JsonStream;
-
 /// Represents different types of models available in the deep seek.
 #[derive(Default, Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub enum ModelType {
@@ -14,23 +15,67 @@ pub enum ModelType {
     /// Model type for reasoning-based interactions.
     #[serde(rename = "deepseek-reasoner")]
     DeepSeekReasoner,
+
+    /// A model id not known to this crate, kept as sent by the server.
+    #[serde(untagged)]
+    Unknown(String),
 }
 
 impl ModelType {
-    /// Retrieves the limit information for the model.
+    /// Returns the model id sent to the API.
+    pub fn id(&self) -> &str {
+        match self {
+            ModelType::DeepSeekChat => "deepseek-chat",
+            ModelType::DeepSeekReasoner => "deepseek-reasoner",
+            ModelType::Unknown(id) => id,
+        }
+    }
+
+    /// Returns the capabilities registered for the model, or the default ones if its id was
+    /// never registered.
+    pub fn capabilities(&self) -> ModelCapabilities {
+        ModelCapabilities::lookup(self.id()).unwrap_or_default()
+    }
+
+    /// Returns `true` if the model reasons before answering, like `DeepSeekReasoner`.
+    pub fn is_reasoner(&self) -> bool {
+        self.capabilities().is_reasoner
+    }
+
+    /// Retrieves the limit information for the model from its capabilities.
     ///
     /// Returns a tuple containing:
     /// - `context_len`: Maximum context length unit KB.
     /// - `thought_chain_len`: Optional maximum thought chain length.
     /// - `output_len`: Maximum output length.
     pub fn get_limit_info(&self) -> (u32, Option<u32>, u32) {
-        match self {
-            ModelType::DeepSeekChat => (64, None, 8),
-            ModelType::DeepSeekReasoner => (64, Some(32), 8),
+        let capabilities = self.capabilities();
+        (
+            capabilities.context_length / 1024,
+            capabilities.max_reasoning_tokens.map(|len| len / 1024),
+            capabilities.max_output_tokens / 1024,
+        )
+    }
+}
+
+impl From<&str> for ModelType {
+    /// Maps the ids of the DeepSeek models to their shortcut, and any other id to
+    /// `ModelType::Unknown`.
+    fn from(id: &str) -> Self {
+        match id {
+            "deepseek-chat" => ModelType::DeepSeekChat,
+            "deepseek-reasoner" => ModelType::DeepSeekReasoner,
+            id => ModelType::Unknown(id.to_string()),
         }
     }
 }
 
+impl From<String> for ModelType {
+    fn from(id: String) -> Self {
+        ModelType::from(id.as_str())
+    }
+}
+
 impl fmt::Display for ModelType {
     /// Formats the model type into a human-readable string.
     ///
@@ -53,6 +98,7 @@ impl fmt::Display for ModelType {
                     context_len, thought_chain_len, output_len
                 )
             }
+            ModelType::Unknown(id) => write!(f, "{}", id),
         }
     }
 }
@@ -68,6 +114,13 @@ pub struct Model {
     pub owned_by: String,
 }
 
+impl Model {
+    /// Returns the `ModelType` to request this model with.
+    pub fn model_type(&self) -> ModelType {
+        ModelType::from(self.id.as_str())
+    }
+}
+
 /// Response structure containing a list of models.
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ModelResp {
@@ -109,7 +162,7 @@ pub struct Function {
 }
 
 /// Represents a tool call with its associated function.
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct ToolCall {
     /// Unique identifier for the tool call.
     pub id: String,
@@ -119,18 +172,64 @@ pub struct ToolCall {
     pub function: Function,
 }
 
+impl ToolCall {
+    /// Deserializes the arguments the model sent for this call.
+    ///
+    /// Empty arguments are read as `{}`, which models send for functions without parameters.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ToolArgumentsError` holding the raw arguments if they are not valid JSON for
+    /// `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// if let Some(tool_calls) = &message.tool_calls {
+    ///     let args: GetWeather = tool_calls[0].parse_arguments()?;
+    /// }
+    /// ```
+    pub fn parse_arguments<T: DeserializeOwned>(&self) -> Result<T, ToolArgumentsError> {
+        let arguments = match self.function.arguments.trim() {
+            "" => "{}",
+            arguments => arguments,
+        };
+        serde_json::from_str(arguments).map_err(|source| ToolArgumentsError {
+            name: self.function.name.clone(),
+            arguments: self.function.arguments.clone(),
+            source,
+        })
+    }
+}
+
 /// Represents a message with its content and optional reasoning content and tool calls.
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub struct AssistantMessage {
     /// Content of the message.
     pub content: String,
     /// Optional reasoning content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub reasoning_content: Option<String>,
     /// Optional list of tool calls.
     pub tool_calls: Option<Vec<ToolCall>>,
     pub name: Option<String>,
     #[serde(default)]
     pub prefix: bool,
+    /// Fields sent by the server that this crate does not know about.
+    ///
+    /// They are not sent back when the message is part of a request.
+    #[serde(flatten, skip_serializing, deserialize_with = "deserialize_extra")]
+    pub extra: Map<String, Value>,
+}
+
+/// Reads the unknown fields of a message, leaving out its `role`, which is always `assistant`.
+fn deserialize_extra<'de, D>(deserializer: D) -> Result<Map<String, Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut extra = Map::deserialize(deserializer)?;
+    extra.remove("role");
+    Ok(extra)
 }
 
 impl AssistantMessage {
@@ -190,6 +289,9 @@ pub enum FinishReason {
     /// Process finished due to insufficient system resources.
     #[serde(rename = "insufficient_system_resource")]
     InsufficientSystemResource,
+    /// A reason not known to this crate, kept as sent by the server.
+    #[serde(untagged)]
+    Unknown(String),
 }
 
 /// Wrapper for log probability information.
@@ -236,10 +338,25 @@ pub struct Choice {
     pub message: Option<AssistantMessage>,
     /// Optional log probability information.
     pub logprobs: Option<LogProbWrap>,
+    /// Fields sent by the server that this crate does not know about.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Choice {
+    /// Returns the generated text: the content of the message of a chat completion, or the
+    /// text of a text completion.
+    pub fn content(&self) -> &str {
+        match (&self.message, &self.text) {
+            (Some(message), _) => &message.content,
+            (None, Some(text)) => text,
+            (None, None) => "",
+        }
+    }
 }
 
 /// Represents usage information for a process.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Usage {
     /// Number of completion tokens used.
     pub completion_tokens: u64,
@@ -253,6 +370,27 @@ pub struct Usage {
     pub total_tokens: u64,
     /// Details of completion tokens used.
     pub completion_tokens_details: Option<CompletionTokensDetails>,
+    /// Fields sent by the server that this crate does not know about.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl std::ops::AddAssign<&Usage> for Usage {
+    /// Adds the token counts of `other`; its unknown fields are not merged.
+    fn add_assign(&mut self, other: &Usage) {
+        self.completion_tokens += other.completion_tokens;
+        self.prompt_tokens += other.prompt_tokens;
+        self.prompt_cache_hit_tokens += other.prompt_cache_hit_tokens;
+        self.prompt_cache_miss_tokens += other.prompt_cache_miss_tokens;
+        self.total_tokens += other.total_tokens;
+        if let Some(details) = &other.completion_tokens_details {
+            self.completion_tokens_details
+                .get_or_insert(CompletionTokensDetails {
+                    reasoning_tokens: 0,
+                })
+                .reasoning_tokens += details.reasoning_tokens;
+        }
+    }
 }
 
 /// Details of completion tokens used.
@@ -278,6 +416,29 @@ pub struct ChatCompletion {
     /// Type of the object.
     pub object: String,
     pub usage: Usage,
+    /// Fields sent by the server that this crate does not know about.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ChatCompletion {
+    /// Returns the choice with the given `index`, as requested with `n`.
+    pub fn choice(&self, index: usize) -> Option<&Choice> {
+        self.choices.iter().find(|choice| choice.index == index)
+    }
+
+    /// Iterates over the generated text of every choice, in the order they were returned.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// for (i, text) in completion.texts().enumerate() {
+    ///     println!("{}: {}", i, text);
+    /// }
+    /// ```
+    pub fn texts(&self) -> impl Iterator<Item = &str> {
+        self.choices.iter().map(Choice::content)
+    }
 }
 
 /// Represents a delta change in a choice stream.
@@ -291,6 +452,132 @@ pub struct Delta {
     /// Role of the delta change sender.
     #[serde(default)]
     pub role: String,
+    /// Fragments of the tool calls being streamed.
+    #[serde(default)]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+/// A fragment of a tool call received in a streamed `Delta`.
+///
+/// The first fragment of a call usually carries its `id`, type and function name, and the
+/// following ones only carry more of the arguments. Fragments belong to the call at `index`.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ToolCallDelta {
+    /// Index of the tool call this fragment belongs to.
+    pub index: usize,
+    /// Unique identifier for the tool call.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Type of the tool call.
+    #[serde(default, rename = "type")]
+    pub tool_type: Option<String>,
+    /// Fragment of the function associated with the tool call.
+    #[serde(default)]
+    pub function: Option<FunctionDelta>,
+}
+
+/// A fragment of a function received in a streamed tool call.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct FunctionDelta {
+    /// Name of the function.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Part of the arguments of the function.
+    #[serde(default)]
+    pub arguments: Option<String>,
+}
+
+/// Stitches streamed `ToolCallDelta` fragments into complete `ToolCall` values.
+///
+/// Fragments are grouped by their `index`: ids, types and names are taken from the first
+/// fragment carrying them, and argument fragments are appended in order. One accumulator
+/// collects the tool calls of a single choice.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut accumulator = ToolCallAccumulator::new();
+/// while let Some(chunk) = stream.next().await {
+///     for choice in chunk?.choices {
+///         if let Some(tool_calls) = accumulator.push(&choice) {
+///             println!("{:?}", tool_calls);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ToolCallAccumulator {
+    calls: BTreeMap<usize, ToolCall>,
+}
+
+impl ToolCallAccumulator {
+    /// Creates an empty `ToolCallAccumulator`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the tool call fragments of a streamed choice.
+    ///
+    /// # Returns
+    ///
+    /// The complete tool calls, ordered by index, once the choice finishes with
+    /// `FinishReason::ToolCalls`, and `None` otherwise.
+    pub fn push(&mut self, choice: &JSONChoiceStream) -> Option<Vec<ToolCall>> {
+        self.push_delta(&choice.delta);
+        match choice.finish_reason {
+            Some(FinishReason::ToolCalls) => Some(self.take()),
+            _ => None,
+        }
+    }
+
+    /// Adds the tool call fragments of a `Delta`.
+    pub fn push_delta(&mut self, delta: &Delta) {
+        for fragment in delta.tool_calls.iter().flatten() {
+            let call = self
+                .calls
+                .entry(fragment.index)
+                .or_insert_with(|| ToolCall {
+                    id: String::new(),
+                    tool_type: String::new(),
+                    function: Function {
+                        name: String::new(),
+                        arguments: String::new(),
+                    },
+                });
+            if let Some(id) = fragment.id.as_ref().filter(|_| call.id.is_empty()) {
+                call.id = id.clone();
+            }
+            if let Some(tool_type) = fragment
+                .tool_type
+                .as_ref()
+                .filter(|_| call.tool_type.is_empty())
+            {
+                call.tool_type = tool_type.clone();
+            }
+            if let Some(function) = &fragment.function {
+                if let Some(name) = function
+                    .name
+                    .as_ref()
+                    .filter(|_| call.function.name.is_empty())
+                {
+                    call.function.name = name.clone();
+                }
+                if let Some(arguments) = &function.arguments {
+                    call.function.arguments.push_str(arguments);
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if no fragment has been received since the last call to `take`.
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// Returns the tool calls collected so far, ordered by index, and resets the accumulator.
+    pub fn take(&mut self) -> Vec<ToolCall> {
+        std::mem::take(&mut self.calls).into_values().collect()
+    }
 }
 
 /// Represents a choice stream with its associated delta change.
@@ -302,6 +589,9 @@ pub struct JSONChoiceStream {
     pub finish_reason: Option<FinishReason>,
     /// Index of the choice stream.
     pub index: usize,
+    /// Optional log probability information of the tokens in this delta.
+    #[serde(default)]
+    pub logprobs: Option<LogProbWrap>,
 }
 
 /// Represents a choice stream with its associated delta change.
@@ -313,6 +603,70 @@ pub struct TextChoiceStream {
     pub finish_reason: Option<FinishReason>,
     /// Index of the choice stream.
     pub index: usize,
+    /// Optional log probability information of the tokens in this delta.
+    #[serde(default)]
+    pub logprobs: Option<LogProbWrap>,
+}
+
+/// A choice of a streamed chunk, either a `JSONChoiceStream` or a `TextChoiceStream`.
+///
+/// This is what `CompletionAccumulator` needs to know about a streamed choice to fold it
+/// back into a `Choice`.
+pub trait StreamChoice {
+    /// Index of the choice this fragment belongs to.
+    fn index(&self) -> usize;
+    /// Reason for finishing the choice, set on its last fragment.
+    fn finish_reason(&self) -> Option<&FinishReason>;
+    /// Log probability information of the tokens in this fragment.
+    fn logprobs(&self) -> Option<&LogProbWrap>;
+    /// The message delta of a chat completion, `None` for a text completion.
+    fn delta(&self) -> Option<&Delta>;
+    /// The text of a text completion, `None` for a chat completion.
+    fn text(&self) -> Option<&str>;
+}
+
+impl StreamChoice for JSONChoiceStream {
+    fn index(&self) -> usize {
+        self.index
+    }
+
+    fn finish_reason(&self) -> Option<&FinishReason> {
+        self.finish_reason.as_ref()
+    }
+
+    fn logprobs(&self) -> Option<&LogProbWrap> {
+        self.logprobs.as_ref()
+    }
+
+    fn delta(&self) -> Option<&Delta> {
+        Some(&self.delta)
+    }
+
+    fn text(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl StreamChoice for TextChoiceStream {
+    fn index(&self) -> usize {
+        self.index
+    }
+
+    fn finish_reason(&self) -> Option<&FinishReason> {
+        self.finish_reason.as_ref()
+    }
+
+    fn logprobs(&self) -> Option<&LogProbWrap> {
+        self.logprobs.as_ref()
+    }
+
+    fn delta(&self) -> Option<&Delta> {
+        None
+    }
+
+    fn text(&self) -> Option<&str> {
+        Some(&self.text)
+    }
 }
 
 /// Represents a chat completion stream with its associated metadata.
@@ -330,40 +684,193 @@ pub struct ChatCompletionStream<T> {
     pub system_fingerprint: String,
     /// Type of the object.
     pub object: String,
+    /// Usage information, only set on the final chunk when `include_usage` is requested.
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+/// The metadata of a streamed completion, taken from its first chunk.
+#[derive(Clone, Debug)]
+struct StreamHeader {
+    id: String,
+    created: u32,
+    model: String,
+    system_fingerprint: String,
+    object: String,
+}
+
+/// The state of a single choice while its fragments are being folded.
+#[derive(Clone, Debug, Default)]
+struct ChoiceState {
+    is_chat: bool,
+    content: String,
+    reasoning_content: Option<String>,
+    tool_calls: ToolCallAccumulator,
+    logprobs: Option<Vec<LogProb>>,
+    finish_reason: Option<FinishReason>,
+}
+
+/// Folds the chunks of a streamed completion back into a `ChatCompletion`.
+///
+/// The result has the same shape as a non-streaming response: for every choice index the
+/// content, reasoning content, tool calls and log probabilities are concatenated, and the
+/// usage of the final usage chunk is kept. `JsonStream::collect_completion` drives it over a
+/// whole stream; use it directly to both display the chunks and keep the full response.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut accumulator = CompletionAccumulator::new();
+/// for chunk in stream {
+///     let chunk = chunk?;
+///     print!("{:?}", chunk.choices);
+///     accumulator.push(chunk);
+/// }
+/// let completion = accumulator.finish()?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CompletionAccumulator {
+    header: Option<StreamHeader>,
+    choices: BTreeMap<usize, ChoiceState>,
+    usage: Option<Usage>,
+}
+
+impl CompletionAccumulator {
+    /// Creates an empty `CompletionAccumulator`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a streamed chunk.
+    pub fn push<T: StreamChoice>(&mut self, chunk: ChatCompletionStream<T>) {
+        if self.header.is_none() {
+            self.header = Some(StreamHeader {
+                id: chunk.id,
+                created: chunk.created,
+                model: chunk.model,
+                system_fingerprint: chunk.system_fingerprint,
+                object: chunk.object,
+            });
+        }
+        if chunk.usage.is_some() {
+            self.usage = chunk.usage;
+        }
+
+        for choice in chunk.choices {
+            let state = self.choices.entry(choice.index()).or_default();
+            if let Some(delta) = choice.delta() {
+                state.is_chat = true;
+                if let Some(content) = &delta.content {
+                    state.content.push_str(content);
+                }
+                if let Some(reasoning_content) = &delta.reasoning_content {
+                    state
+                        .reasoning_content
+                        .get_or_insert_with(String::new)
+                        .push_str(reasoning_content);
+                }
+                state.tool_calls.push_delta(delta);
+            }
+            if let Some(text) = choice.text() {
+                state.content.push_str(text);
+            }
+            if let Some(logprobs) = choice.logprobs() {
+                state
+                    .logprobs
+                    .get_or_insert_with(Vec::new)
+                    .extend(logprobs.content.iter().cloned());
+            }
+            if let Some(finish_reason) = choice.finish_reason() {
+                state.finish_reason = Some(finish_reason.clone());
+            }
+        }
+    }
+
+    /// Returns the assembled `ChatCompletion`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StreamError::Incomplete` if no chunk was received, or if a choice never
+    /// received its `finish_reason`.
+    pub fn finish(self) -> Result<ChatCompletion, StreamError> {
+        let header = self
+            .header
+            .ok_or_else(|| StreamError::Incomplete("no chunk received".to_string()))?;
+
+        let choices = self
+            .choices
+            .into_iter()
+            .map(|(index, mut state)| {
+                let finish_reason = state.finish_reason.take().ok_or_else(|| {
+                    StreamError::Incomplete(format!("choice {} has no finish_reason", index))
+                })?;
+                let logprobs = state.logprobs.map(|content| LogProbWrap { content });
+                let (text, message) = if state.is_chat {
+                    let tool_calls = state.tool_calls.take();
+                    let message = AssistantMessage {
+                        content: state.content,
+                        reasoning_content: state.reasoning_content,
+                        tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                        ..Default::default()
+                    };
+                    (None, Some(message))
+                } else {
+                    (Some(state.content), None)
+                };
+                Ok(Choice {
+                    finish_reason,
+                    index,
+                    text,
+                    message,
+                    logprobs,
+                    extra: Map::new(),
+                })
+            })
+            .collect::<Result<_, StreamError>>()?;
+
+        let object = match header.object.strip_suffix(".chunk") {
+            Some(object) => object.to_string(),
+            None => header.object,
+        };
+        Ok(ChatCompletion {
+            id: header.id,
+            choices,
+            created: header.created,
+            model: header.model,
+            system_fingerprint: header.system_fingerprint,
+            object,
+            usage: self.usage.unwrap_or_default(),
+            extra: Map::new(),
+        })
+    }
 }
 
 /// Represents a chat response which can either be a full response or a stream of items.
 ///
-/// This enum is generic over the response type `RESP` and the item type `ITEM`.
+/// This enum is generic over the response type `RESP` and the stream type `STREAM`. Each
+/// client exposes a `ChatResponse` alias that fixes `STREAM` to its own `JsonStream`, e.g.
+/// `deepseek_api::blocking::ChatResponse<RESP, ITEM>`.
 ///
 /// # Variants
 ///
 /// - `Full(RESP)`: Represents a complete response of type `RESP`.
-/// - `Stream(JsonStream<ITEM>)`: Represents a stream of items of type `ITEM`.
+/// - `Stream(STREAM)`: Represents a stream of items.
 ///
 /// # Type Parameters
 ///
-/// - `RESP`: The type of the full response. Must implement `DeserializeOwned`.
-/// - `ITEM`: The type of the items in the stream. Must implement `DeserializeOwned`.
+/// - `RESP`: The type of the full response.
+/// - `STREAM`: The type of the stream, a `JsonStream` of the async or the blocking client.
 ///
 /// # Methods
 ///
 /// - `must_response(self) -> RESP`: Consumes the enum and returns the full response if it is the `Full` variant. Panics if it is the `Stream` variant.
-/// - `must_stream(self) -> JsonStream<ITEM>`: Consumes the enum and returns the stream if it is the `Stream` variant. Panics if it is the `Full` variant.
-pub enum ChatResponse<RESP, ITEM>
-where
-    RESP: DeserializeOwned,
-    ITEM: DeserializeOwned,
-{
+/// - `must_stream(self) -> STREAM`: Consumes the enum and returns the stream if it is the `Stream` variant. Panics if it is the `Full` variant.
+pub enum ChatResponse<RESP, STREAM> {
     Full(RESP),
-    Stream(JsonStream<ITEM>),
+    Stream(STREAM),
 }
 
-impl<RESP, ITEM> ChatResponse<RESP, ITEM>
-where
-    RESP: DeserializeOwned,
-    ITEM: DeserializeOwned,
-{
+impl<RESP, STREAM> ChatResponse<RESP, STREAM> {
     pub fn must_response(self) -> RESP {
         match self {
             ChatResponse::Full(resp) => resp,
@@ -371,7 +878,7 @@ where
         }
     }
 
-    pub fn must_stream(self) -> JsonStream<ITEM> {
+    pub fn must_stream(self) -> STREAM {
         match self {
             ChatResponse::Stream(stream) => stream,
             ChatResponse::Full(_) => panic!("Expected Stream variant, found Full"),
@@ -384,6 +891,91 @@ mod tests {
     use super::*;
     use serde_json::json;
 
+    #[test]
+    fn test_unknown_values_are_kept() {
+        let completion: ChatCompletion = serde_json::from_value(json!({
+            "id": "c1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "deepseek-chat",
+            "system_fingerprint": "fp",
+            "service_tier": "default",
+            "choices": [{
+                "index": 0,
+                "finish_reason": "model_context_window_exceeded",
+                "message": {"role": "assistant", "content": "Hi", "annotations": []},
+                "stop_reason": 7
+            }],
+            "usage": {
+                "completion_tokens": 1,
+                "prompt_tokens": 1,
+                "prompt_cache_hit_tokens": 0,
+                "prompt_cache_miss_tokens": 1,
+                "total_tokens": 2,
+                "audio_tokens": 0
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(completion.extra["service_tier"], "default");
+        let choice = &completion.choices[0];
+        assert_eq!(
+            choice.finish_reason,
+            FinishReason::Unknown("model_context_window_exceeded".to_string())
+        );
+        assert_eq!(choice.extra["stop_reason"], 7);
+        let message = choice.message.as_ref().unwrap();
+        assert_eq!(message.extra["annotations"], json!([]));
+        assert_eq!(completion.usage.extra["audio_tokens"], 0);
+
+        let value = serde_json::to_value(&completion).unwrap();
+        assert_eq!(value["service_tier"], "default");
+        assert_eq!(
+            value["choices"][0]["finish_reason"],
+            "model_context_window_exceeded"
+        );
+        assert!(value["choices"][0]["message"].get("annotations").is_none());
+
+        let model: ModelType = serde_json::from_value(json!("deepseek-v9")).unwrap();
+        assert_eq!(model, ModelType::Unknown("deepseek-v9".to_string()));
+        assert_eq!(serde_json::to_value(&model).unwrap(), "deepseek-v9");
+        let model: ModelType = serde_json::from_value(json!("deepseek-reasoner")).unwrap();
+        assert_eq!(model, ModelType::DeepSeekReasoner);
+    }
+
+    #[test]
+    fn test_chat_completion_choices() {
+        let completion: ChatCompletion = serde_json::from_value(json!({
+            "id": "c1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "deepseek-chat",
+            "system_fingerprint": "fp",
+            "choices": [
+                {"index": 1, "finish_reason": "stop", "message": {"role": "assistant", "content": "Hi"}},
+                {"index": 0, "finish_reason": "stop", "message": {"role": "assistant", "content": "Hello"}},
+                {"index": 2, "finish_reason": "length", "text": "Hey"}
+            ],
+            "usage": {
+                "completion_tokens": 3,
+                "prompt_tokens": 1,
+                "prompt_cache_hit_tokens": 0,
+                "prompt_cache_miss_tokens": 1,
+                "total_tokens": 4
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(completion.choice(0).unwrap().content(), "Hello");
+        let message = completion.choice(0).unwrap().message.as_ref().unwrap();
+        assert!(message.extra.is_empty());
+        assert!(completion.choice(3).is_none());
+        assert_eq!(
+            completion.texts().collect::<Vec<_>>(),
+            vec!["Hi", "Hello", "Hey"]
+        );
+    }
+
     #[test]
     fn test_deserialize_chat_completion_stream() {
         let json_data = json!({
@@ -423,4 +1015,93 @@ mod tests {
         assert_eq!(choice.delta.reasoning_content.as_ref().unwrap(), "");
         assert!(choice.finish_reason.is_none());
     }
+
+    #[test]
+    fn test_accumulate_streamed_tool_calls() {
+        let chunks = [
+            json!({"index": 0, "delta": {"role": "assistant", "content": null, "tool_calls": [
+                {"index": 0, "id": "call_0", "type": "function", "function": {"name": "get_weather", "arguments": ""}}
+            ]}, "finish_reason": null}),
+            json!({"index": 0, "delta": {"content": null, "tool_calls": [
+                {"index": 0, "function": {"arguments": "{\"location\":"}}
+            ]}, "finish_reason": null}),
+            json!({"index": 0, "delta": {"content": null, "tool_calls": [
+                {"index": 1, "id": "call_1", "type": "function", "function": {"name": "get_time", "arguments": "{}"}},
+                {"index": 0, "function": {"arguments": " \"Paris\"}"}}
+            ]}, "finish_reason": null}),
+            json!({"index": 0, "delta": {"content": ""}, "finish_reason": "tool_calls"}),
+        ];
+
+        let mut accumulator = ToolCallAccumulator::new();
+        let mut completed = None;
+        for chunk in chunks {
+            let choice: JSONChoiceStream = serde_json::from_value(chunk).unwrap();
+            assert!(completed.is_none());
+            completed = accumulator.push(&choice);
+        }
+
+        let tool_calls = completed.unwrap();
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0].id, "call_0");
+        assert_eq!(tool_calls[0].tool_type, "function");
+        assert_eq!(tool_calls[0].function.name, "get_weather");
+        assert_eq!(tool_calls[0].function.arguments, r#"{"location": "Paris"}"#);
+        assert_eq!(tool_calls[1].id, "call_1");
+        assert_eq!(tool_calls[1].function.name, "get_time");
+        assert_eq!(tool_calls[1].function.arguments, "{}");
+        assert!(accumulator.is_empty());
+    }
+
+    #[test]
+    fn test_deserialize_stream_logprobs() {
+        let json_data = json!({
+            "index": 0,
+            "delta": {"content": "Hi"},
+            "logprobs": {"content": [
+                {"token": "Hi", "logprob": -0.5, "bytes": [72, 105], "top_logprobs": []}
+            ]},
+            "finish_reason": null
+        });
+
+        let choice: JSONChoiceStream = serde_json::from_value(json_data).unwrap();
+        let logprobs = choice.logprobs.unwrap();
+        assert_eq!(logprobs.content[0].token, "Hi");
+        assert_eq!(logprobs.content[0].logprob, -0.5);
+    }
+
+    #[test]
+    fn test_parse_tool_call_arguments() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct GetWeather {
+            location: String,
+        }
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct GetTime {}
+
+        let mut call = ToolCall {
+            id: "call_0".to_string(),
+            tool_type: "function".to_string(),
+            function: Function {
+                name: "get_weather".to_string(),
+                arguments: r#"{"location": "Paris"}"#.to_string(),
+            },
+        };
+        assert_eq!(
+            call.parse_arguments::<GetWeather>().unwrap(),
+            GetWeather {
+                location: "Paris".to_string()
+            }
+        );
+
+        call.function.arguments = r#"{"location": "Par"#.to_string();
+        let err = call.parse_arguments::<GetWeather>().unwrap_err();
+        assert_eq!(err.name, "get_weather");
+        assert_eq!(err.arguments, r#"{"location": "Par"#);
+        assert!(err
+            .to_string()
+            .starts_with("invalid arguments for `get_weather`"));
+
+        call.function.arguments = String::new();
+        assert_eq!(call.parse_arguments::<GetTime>().unwrap(), GetTime {});
+    }
 }
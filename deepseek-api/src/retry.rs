@@ -0,0 +1,200 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use http::header::{HeaderMap, RETRY_AFTER};
+
+/// Controls how `DeepSeekClient` retries requests that failed with a transient error.
+///
+/// A request is retried when the server answers with an error for which
+/// [`ApiError::is_retryable`](crate::ApiError::is_retryable) holds (such as `429`, `500` or
+/// `503`), or when the connection could not be established. Once a response with a success
+/// status has been received the request is never retried, so a streaming body is never
+/// replayed.
+///
+/// The delay before attempt `n + 1` is `base_delay * 2^(n - 1)`, capped at `max_delay`,
+/// with up to `jitter` of it randomly subtracted. A `Retry-After` header (in seconds) sent by
+/// the server takes precedence over the computed delay, and is also capped at `max_delay`.
+///
+/// # Examples
+///
+/// ```ignore
+/// let client = DeepSeekClientBuilder::new("your_api_key".to_string())
+///     .with_retry_policy(RetryPolicy::default().with_max_attempts(5))
+///     .build()
+///     .expect("Failed to build client");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    /// Returns a policy with 3 attempts, a 500ms base delay, a 30s cap and 20% jitter.
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns a policy that sends every request exactly once.
+    pub fn never() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the total number of attempts, including the first one. `0` is treated as `1`.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Sets the upper bound of any single delay, including one requested by `Retry-After`.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Sets the fraction of the delay, between 0 and 1, that may be randomly removed.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns the total number of attempts, including the first one.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns the delay to wait after the failed attempt number `attempt` (starting at 1).
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - The number of the attempt that just failed.
+    /// * `retry_after` - The delay requested by the server, if any.
+    pub fn delay_for(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+
+        let exp = attempt.saturating_sub(1).min(31);
+        let backoff = self
+            .base_delay
+            .saturating_mul(1u32 << exp)
+            .min(self.max_delay);
+        backoff.mul_f64(1.0 - self.jitter * random_fraction())
+    }
+
+    #[cfg_attr(not(any(feature = "is_async", feature = "is_sync")), allow(dead_code))]
+    pub(crate) fn should_retry(&self, attempt: u32) -> bool {
+        attempt < self.max_attempts
+    }
+}
+
+/// Reads a `Retry-After` header expressed in seconds.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exponential_backoff_is_capped() {
+        let policy = RetryPolicy::default()
+            .with_base_delay(Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(350))
+            .with_jitter(0.0);
+
+        assert_eq!(policy.delay_for(1, None), Duration::from_millis(100));
+        assert_eq!(policy.delay_for(2, None), Duration::from_millis(200));
+        assert_eq!(policy.delay_for(3, None), Duration::from_millis(350));
+        assert_eq!(policy.delay_for(40, None), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn test_jitter_stays_within_bounds() {
+        let policy = RetryPolicy::default()
+            .with_base_delay(Duration::from_millis(1000))
+            .with_jitter(0.5);
+
+        for _ in 0..100 {
+            let delay = policy.delay_for(1, None);
+            assert!(delay <= Duration::from_millis(1000));
+            assert!(delay >= Duration::from_millis(500));
+        }
+    }
+
+    #[test]
+    fn test_retry_after_takes_precedence() {
+        let policy = RetryPolicy::default().with_max_delay(Duration::from_secs(10));
+
+        assert_eq!(
+            policy.delay_for(1, Some(Duration::from_secs(3))),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            policy.delay_for(1, Some(Duration::from_secs(60))),
+            Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, "2".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(2)));
+
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn test_max_attempts() {
+        let policy = RetryPolicy::never();
+        assert!(!policy.should_retry(1));
+
+        let policy = RetryPolicy::default().with_max_attempts(0);
+        assert_eq!(policy.max_attempts(), 1);
+
+        let policy = RetryPolicy::default().with_max_attempts(3);
+        assert!(policy.should_retry(2));
+        assert!(!policy.should_retry(3));
+    }
+}
@@ -0,0 +1,285 @@
+use crate::response::Usage;
+use crate::{ApiError, Error, ErrorResponse, StreamError};
+use http::HeaderMap;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::VecDeque;
+
+/// The data sent by the server to mark the end of a streaming response.
+const DONE: &str = "[DONE]";
+
+/// The UTF-8 byte order mark, skipped at the start of a stream.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// An event dispatched by an `EventParser`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Event {
+    /// The event type, `message` unless an `event` field was set.
+    pub(crate) event: String,
+    /// The `data` fields of the event, joined by newlines.
+    pub(crate) data: String,
+    /// The last event id seen in the stream, if any.
+    pub(crate) id: Option<String>,
+    /// The reconnection time requested by the last `retry` field, in milliseconds.
+    pub(crate) retry: Option<u64>,
+}
+
+/// An incremental parser for `text/event-stream` bodies.
+///
+/// It follows the [WHATWG event stream interpretation](https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation):
+/// lines may end with CRLF, LF or CR, a leading BOM is skipped, comments and unknown fields
+/// are ignored, `data` fields are joined by newlines and an event is dispatched on each
+/// blank line. Bytes may be fed in chunks of any size.
+#[derive(Debug, Default)]
+pub(crate) struct EventParser {
+    line: Vec<u8>,
+    started: bool,
+    after_cr: bool,
+    data: String,
+    event: Option<String>,
+    id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl EventParser {
+    /// Parses a chunk of the body, appending the dispatched events to `events`.
+    pub(crate) fn feed(&mut self, bytes: &[u8], events: &mut VecDeque<Event>) {
+        if !self.started {
+            let mut head = std::mem::take(&mut self.line);
+            head.extend_from_slice(bytes);
+            if head.len() < BOM.len() && BOM.starts_with(&head) {
+                self.line = head;
+                return;
+            }
+
+            self.started = true;
+            let head = head.strip_prefix(BOM).unwrap_or(&head);
+            self.process_bytes(head, events);
+        } else {
+            self.process_bytes(bytes, events);
+        }
+    }
+
+    fn process_bytes(&mut self, bytes: &[u8], events: &mut VecDeque<Event>) {
+        for &byte in bytes {
+            match byte {
+                b'\n' if self.after_cr => self.after_cr = false,
+                b'\n' | b'\r' => {
+                    self.after_cr = byte == b'\r';
+                    let line = std::mem::take(&mut self.line);
+                    self.process_line(&String::from_utf8_lossy(&line), events);
+                }
+                _ => {
+                    self.after_cr = false;
+                    self.line.push(byte);
+                }
+            }
+        }
+    }
+
+    fn process_line(&mut self, line: &str, events: &mut VecDeque<Event>) {
+        if line.is_empty() {
+            self.dispatch(events);
+            return;
+        }
+        if line.starts_with(':') {
+            return;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.id = Some(value.to_string()),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                self.retry = value.parse().ok();
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self, events: &mut VecDeque<Event>) {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return;
+        }
+
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+        events.push_back(Event {
+            event: event.unwrap_or_else(|| "message".to_string()),
+            data,
+            id: self.id.clone(),
+            retry: self.retry,
+        });
+    }
+}
+
+/// Decodes the JSON data of an event.
+///
+/// # Returns
+///
+/// `Ok(None)` for the `[DONE]` event that ends the stream, and the decoded item otherwise.
+///
+/// # Errors
+///
+/// Returns `Error::Api` if the event is an `error` event or carries an
+/// `{"error": {...}}` payload, and `StreamError::Json` if the data cannot be decoded.
+pub(crate) fn decode_event<T: DeserializeOwned>(event: Event) -> Result<Option<T>, Error> {
+    if event.data == DONE {
+        return Ok(None);
+    }
+    if event.event == "error" || is_error_payload(&event.data) {
+        return Err(stream_api_error(event.data).into());
+    }
+
+    match serde_json::from_str(&event.data) {
+        Ok(item) => Ok(Some(item)),
+        Err(source) => Err(StreamError::Json {
+            data: event.data,
+            source,
+        }
+        .into()),
+    }
+}
+
+#[derive(Deserialize)]
+struct UsageChunk {
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+/// Reads the `usage` field of the JSON data of an event, if it has a non-null one.
+pub(crate) fn event_usage(event: &Event) -> Option<Usage> {
+    if !event.data.contains("\"usage\"") {
+        return None;
+    }
+    serde_json::from_str::<UsageChunk>(&event.data)
+        .ok()
+        .and_then(|chunk| chunk.usage)
+}
+
+/// Returns `true` if `data` is an `{"error": {...}}` object.
+fn is_error_payload(data: &str) -> bool {
+    data.contains("\"error\"")
+        && serde_json::from_str::<serde_json::Value>(data)
+            .is_ok_and(|value| value.get("error").is_some_and(|error| error.is_object()))
+}
+
+/// Builds the `ApiError` reported by the server in the middle of a stream.
+///
+/// The response status was already successful when the error was sent, so the status is
+/// taken from a numeric `code` in the payload and defaults to `500`.
+fn stream_api_error(data: String) -> ApiError {
+    let mut resp = ErrorResponse::new(500, &HeaderMap::new(), data);
+    if let Some(status) = resp
+        .code
+        .as_deref()
+        .and_then(|code| code.parse::<u16>().ok())
+        .filter(|status| (400..600).contains(status))
+    {
+        resp.status = status;
+    }
+    resp.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(chunks: &[&[u8]]) -> Vec<Event> {
+        let mut parser = EventParser::default();
+        let mut events = VecDeque::new();
+        for chunk in chunks {
+            parser.feed(chunk, &mut events);
+        }
+        events.into()
+    }
+
+    fn data(events: &[Event]) -> Vec<&str> {
+        events.iter().map(|event| event.data.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fields_and_comments() {
+        let events = parse(&[
+            b": keep-alive\n\nevent: delta\nid: 7\nretry: 1000\ndata:{\"a\":1}\nunknown: x\n\n",
+        ]);
+
+        assert_eq!(
+            events,
+            vec![Event {
+                event: "delta".to_string(),
+                data: "{\"a\":1}".to_string(),
+                id: Some("7".to_string()),
+                retry: Some(1000),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_multi_line_data() {
+        let events = parse(&[b"data: first\ndata: second\ndata\n\ndata: third\n\n"]);
+        assert_eq!(data(&events), vec!["first\nsecond\n", "third"]);
+        assert_eq!(events[1].event, "message");
+    }
+
+    #[test]
+    fn test_line_endings_across_chunks() {
+        let events = parse(&[b"data: a\r", b"\n\r", b"\ndata: b\r\rdata: c\n", b"\n"]);
+        assert_eq!(data(&events), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_bom_and_incomplete_event() {
+        let events = parse(&[b"\xEF\xBB", b"\xBFdata: a\n\ndata: b\n"]);
+        assert_eq!(data(&events), vec!["a"]);
+
+        let events = parse(&[b"\xEF\xBBdata: a\n\n"]);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_decode_error_payload() {
+        let event = Event {
+            event: "message".to_string(),
+            data: r#"{"error":{"message":"overloaded","type":"server_error","code":503}}"#
+                .to_string(),
+            id: None,
+            retry: None,
+        };
+
+        let err = decode_event::<serde_json::Value>(event).unwrap_err();
+        match err {
+            Error::Api(ApiError::ServiceUnavailable(resp)) => {
+                assert_eq!(resp.message, "overloaded")
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_decode_done_and_error_event() {
+        let done = Event {
+            event: "message".to_string(),
+            data: DONE.to_string(),
+            id: None,
+            retry: None,
+        };
+        assert!(decode_event::<serde_json::Value>(done).unwrap().is_none());
+
+        let error = Event {
+            event: "error".to_string(),
+            data: "stream interrupted".to_string(),
+            id: None,
+            retry: None,
+        };
+        let err = decode_event::<serde_json::Value>(error).unwrap_err();
+        assert!(matches!(err, Error::Api(ApiError::ServerError(_))));
+    }
+}
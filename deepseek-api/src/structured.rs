@@ -0,0 +1,129 @@
+use crate::request::{schema_for, MessageRequest};
+use crate::response::{AssistantMessage, ChatCompletion};
+use crate::StructuredOutputError;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+
+/// The default number of requests sent by `complete_json`.
+pub(crate) const DEFAULT_MAX_ATTEMPTS: usize = 3;
+
+/// Asks for a JSON reply matching the schema of `T`, in a system message placed before
+/// `messages`, or appended to the system message `messages` start with.
+pub(crate) fn json_messages<T: JsonSchema>(messages: &[MessageRequest]) -> Vec<MessageRequest> {
+    let schema = serde_json::to_string(&schema_for::<T>()).expect("a JSON Schema is valid JSON");
+    let prompt = format!(
+        "Reply with a single JSON object and no other text. The JSON must be valid against \
+         this JSON Schema:\n{}",
+        schema
+    );
+
+    let mut json_messages = Vec::with_capacity(messages.len() + 1);
+    match messages.first() {
+        Some(MessageRequest::System(system)) => {
+            let mut system = system.clone();
+            system.content = format!("{}\n\n{}", system.content, prompt);
+            json_messages.push(MessageRequest::System(system));
+            json_messages.extend_from_slice(&messages[1..]);
+        }
+        _ => {
+            json_messages.push(MessageRequest::sys(&prompt));
+            json_messages.extend_from_slice(messages);
+        }
+    }
+    json_messages
+}
+
+/// Decodes the content of the first choice of `completion` into `T`.
+///
+/// # Errors
+///
+/// Returns the content with the decoding error if it is not valid JSON for `T`.
+pub(crate) fn parse_reply<T: DeserializeOwned>(
+    completion: &ChatCompletion,
+) -> Result<T, (String, serde_json::Error)> {
+    let raw = completion
+        .choices
+        .first()
+        .and_then(|choice| choice.message.as_ref())
+        .map(|message| message.content.clone())
+        .unwrap_or_default();
+    serde_json::from_str(&raw).map_err(|err| (raw, err))
+}
+
+/// Handles a reply that could not be decoded after `attempt` requests.
+///
+/// # Errors
+///
+/// Returns a `StructuredOutputError` once `max_attempts` requests were sent; otherwise the
+/// invalid reply and a request to repair it are appended to `messages`.
+pub(crate) fn repair(
+    messages: &mut Vec<MessageRequest>,
+    attempt: usize,
+    max_attempts: usize,
+    raw: String,
+    source: serde_json::Error,
+) -> Result<(), StructuredOutputError> {
+    if attempt >= max_attempts {
+        return Err(StructuredOutputError {
+            attempts: attempt,
+            raw,
+            source,
+        });
+    }
+
+    let prompt = format!(
+        "Your reply could not be parsed: {}. Reply again with only the corrected JSON object.",
+        source
+    );
+    messages.push(MessageRequest::Assistant(AssistantMessage::new(&raw)));
+    messages.push(MessageRequest::user(&prompt));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize, JsonSchema)]
+    #[allow(dead_code)]
+    struct City {
+        name: String,
+    }
+
+    #[test]
+    fn test_json_messages() {
+        let messages = json_messages::<City>(&[MessageRequest::user("Name a city")]);
+
+        assert_eq!(messages.len(), 2);
+        let prompt = messages[0].get_content();
+        assert!(prompt.contains("JSON"));
+        assert!(prompt.contains(r#""required":["name"]"#));
+        assert_eq!(messages[1].get_content(), "Name a city");
+
+        let messages = json_messages::<City>(&[
+            MessageRequest::sys("Be brief."),
+            MessageRequest::user("Name a city"),
+        ]);
+        assert_eq!(messages.len(), 2);
+        let prompt = messages[0].get_content();
+        assert!(prompt.starts_with("Be brief.\n\n"));
+        assert!(prompt.contains(r#""required":["name"]"#));
+        assert_eq!(messages[1].get_content(), "Name a city");
+    }
+
+    #[test]
+    fn test_repair() {
+        let mut messages = Vec::new();
+        let source = serde_json::from_str::<City>("{}").unwrap_err();
+        repair(&mut messages, 1, 2, "{}".to_string(), source).unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].get_content(), "{}");
+        assert!(messages[1].get_content().contains("missing field `name`"));
+
+        let source = serde_json::from_str::<City>("{}").unwrap_err();
+        let err = repair(&mut messages, 2, 2, "{}".to_string(), source).unwrap_err();
+        assert_eq!(err.attempts, 2);
+        assert_eq!(err.raw, "{}");
+    }
+}
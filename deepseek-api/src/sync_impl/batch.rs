@@ -0,0 +1,387 @@
+use super::client::{ChatResponse, DeepSeekClient};
+use crate::batch::{retry_delay, BatchState, Checkpoint, LOOKAHEAD};
+use crate::{
+    response::{ChatCompletion, ChatCompletionStream, JSONChoiceStream, Usage},
+    BatchProgress, CompletionsRequestBuilder, Error, PreparedRequest, Result, RetryPolicy,
+    TransportError, TransportErrorKind,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+type Job = (usize, PreparedRequest);
+
+impl DeepSeekClient {
+    /// Sends many independent completion requests, at most `concurrency` at a time.
+    ///
+    /// The returned iterator yields `(index, result)` for each request, in the order of
+    /// `requests`, where `index` is the position of the request in `requests`. The requests
+    /// are sent by `concurrency` worker threads, each with a clone of the client. Streaming
+    /// requests are collected into a single `ChatCompletion`. Each request goes through the
+    /// retries and the rate limiter of the client; a request that still fails with a transient
+    /// error, or whose response is cut short, is sent again according to the retry policy of
+    /// the batch.
+    ///
+    /// Requests are only built as the iterator is advanced, so `requests` may be a lazy
+    /// iterator over a large input. The results received while an earlier request is still
+    /// pending are held until it completes, and no more than `4 * concurrency` requests are
+    /// started from it onwards. Dropping the iterator lets the workers finish the requests
+    /// already sent, then stop.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests` - The requests to send.
+    /// * `concurrency` - The maximum number of requests in flight at once; `0` is treated as `1`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let conversations: Vec<Vec<MessageRequest>> = load_prompts()?;
+    /// let mut batch = client
+    ///     .complete_batch(conversations.iter().map(|m| CompletionsRequestBuilder::new(m)), 8)
+    ///     .with_checkpoint("prompts.checkpoint")?
+    ///     .on_progress(|progress| eprintln!("{} done", progress.completed));
+    ///
+    /// for (index, result) in &mut batch {
+    ///     save(index, result?)?;
+    /// }
+    /// println!("{} tokens used", batch.usage().total_tokens);
+    /// ```
+    pub fn complete_batch<'a, I>(&self, requests: I, concurrency: usize) -> BatchIter<'a>
+    where
+        I: IntoIterator<Item = CompletionsRequestBuilder<'a>>,
+        I::IntoIter: 'a,
+    {
+        let (results_tx, results) = mpsc::channel();
+        BatchIter {
+            client: self.clone(),
+            requests: Box::new(requests.into_iter()),
+            next_index: 0,
+            concurrency: concurrency.max(1),
+            pending: VecDeque::new(),
+            done: HashMap::new(),
+            jobs: None,
+            results_tx: Some(results_tx),
+            results,
+            state: BatchState::new(),
+        }
+    }
+}
+
+/// The iterator returned by `DeepSeekClient::complete_batch`.
+///
+/// Its settings must be changed before it is first advanced.
+pub struct BatchIter<'a> {
+    client: DeepSeekClient,
+    requests: Box<dyn Iterator<Item = CompletionsRequestBuilder<'a>> + 'a>,
+    next_index: usize,
+    concurrency: usize,
+    /// The indices of the requests started and not yielded yet, in order.
+    pending: VecDeque<usize>,
+    /// The results received before the result of an earlier request.
+    done: HashMap<usize, Result<ChatCompletion>>,
+    jobs: Option<mpsc::Sender<Job>>,
+    /// Handed over to the workers once they are started, so that `results` is disconnected
+    /// if they all stop.
+    results_tx: Option<mpsc::Sender<(usize, Result<ChatCompletion>)>>,
+    results: mpsc::Receiver<(usize, Result<ChatCompletion>)>,
+    state: BatchState<'a>,
+}
+
+impl<'a> BatchIter<'a> {
+    /// Sets how a request failing with a transient error is sent again, 3 attempts in total
+    /// by default.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.state.retry_policy = retry_policy;
+        self
+    }
+
+    /// Calls `on_progress` each time a result is yielded.
+    pub fn on_progress(mut self, on_progress: impl FnMut(&BatchProgress) + Send + 'a) -> Self {
+        self.state.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    /// Resumes the batch from the checkpoint at `path`, creating it if needed.
+    ///
+    /// The requests whose index is listed in the file are skipped, and the index of each
+    /// successful request is appended to it before its result is yielded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or written, or if a line is not an index.
+    pub fn with_checkpoint(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        self.state.checkpoint = Some(Checkpoint::open(path.as_ref())?);
+        Ok(self)
+    }
+
+    /// Returns the progress of the batch so far.
+    pub fn progress(&self) -> &BatchProgress {
+        &self.state.progress
+    }
+
+    /// Returns the usage of the successful requests so far, added up.
+    pub fn usage(&self) -> &Usage {
+        &self.state.progress.usage
+    }
+
+    /// Starts the worker threads, sharing a single queue of jobs.
+    ///
+    /// A worker panicking while sending a request yields an error for that request.
+    fn spawn_workers(&mut self) -> mpsc::Sender<Job> {
+        let (jobs_tx, jobs) = mpsc::channel::<Job>();
+        let jobs = Arc::new(Mutex::new(jobs));
+        let results_tx = self
+            .results_tx
+            .take()
+            .expect("the workers are only started once");
+        for _ in 0..self.concurrency {
+            let jobs = Arc::clone(&jobs);
+            let results = results_tx.clone();
+            let client = self.client.clone();
+            let retry_policy = self.state.retry_policy.clone();
+            thread::spawn(move || loop {
+                let job = jobs.lock().unwrap_or_else(|err| err.into_inner()).recv();
+                let Ok((index, prepared)) = job else {
+                    break;
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    complete(&client, prepared, &retry_policy)
+                }))
+                .unwrap_or_else(|_| Err(worker_stopped()));
+                if results.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        jobs_tx
+    }
+
+    /// Starts requests until `concurrency` of them are in flight, or `LOOKAHEAD` times as
+    /// many are waiting to be yielded.
+    fn fill(&mut self) {
+        let window = self.concurrency.saturating_mul(LOOKAHEAD);
+        // The pending requests whose result is not in `done` are in flight.
+        while self.pending.len() - self.done.len() < self.concurrency && self.pending.len() < window
+        {
+            let Some(request) = self.requests.next() else {
+                break;
+            };
+            let index = self.next_index;
+            self.next_index += 1;
+            if self.state.skip(index) {
+                continue;
+            }
+            self.pending.push_back(index);
+            match PreparedRequest::new(request) {
+                Ok(prepared) => {
+                    if self.jobs.is_none() {
+                        self.jobs = Some(self.spawn_workers());
+                    }
+                    if let Some(jobs) = &self.jobs {
+                        // The workers only stop once `jobs` is dropped.
+                        let _ = jobs.send((index, prepared));
+                    }
+                }
+                Err(err) => {
+                    self.done.insert(index, Err(err));
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for BatchIter<'_> {
+    type Item = (usize, Result<ChatCompletion>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.fill();
+            let index = *self.pending.front()?;
+            if let Some(result) = self.done.remove(&index) {
+                self.pending.pop_front();
+                return Some((index, self.state.finish(index, result)));
+            }
+            let (index, result) = match self.results.recv() {
+                Ok(received) => received,
+                // Every worker has stopped, so the request at `index` is lost.
+                Err(_) => (index, Err(worker_stopped())),
+            };
+            self.done.insert(index, result);
+        }
+    }
+}
+
+/// The error yielded for a request whose worker panicked.
+fn worker_stopped() -> Error {
+    TransportError::new(
+        TransportErrorKind::Other,
+        "the worker thread sending the request panicked",
+    )
+    .into()
+}
+
+fn complete(
+    client: &DeepSeekClient,
+    prepared: PreparedRequest,
+    retry_policy: &RetryPolicy,
+) -> Result<ChatCompletion> {
+    let mut attempt = 1;
+    loop {
+        let err = match send(client, prepared.clone()) {
+            Ok(completion) => return Ok(completion),
+            Err(err) => err,
+        };
+        match retry_delay(retry_policy, attempt, &err) {
+            Some(delay) => thread::sleep(delay),
+            None => return Err(err),
+        }
+        attempt += 1;
+    }
+}
+
+fn send(client: &DeepSeekClient, prepared: PreparedRequest) -> Result<ChatCompletion> {
+    let resp: ChatResponse<ChatCompletion, ChatCompletionStream<JSONChoiceStream>> =
+        client.send_prepared(prepared)?;
+    match resp {
+        ChatResponse::Full(completion) => Ok(completion),
+        ChatResponse::Stream(stream) => stream.collect_completion(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::batch::LOOKAHEAD;
+    use crate::cassette::tests::temp_path;
+    use crate::request::MessageRequest;
+    use crate::sync_impl::fake::{completion, fake_builder, fake_client};
+    use crate::sync_impl::transport::{Body, Transport};
+    use crate::{
+        CompletionsRequestBuilder, DeepSeekClientBuilder, Error, RetryPolicy, TransportError,
+    };
+    use http::Response;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_batch_retries_and_resumes() {
+        let path = temp_path("sync-batch");
+        std::fs::write(&path, "1\n").unwrap();
+        let (builder, requests) = fake_builder(vec![
+            (503, "busy"),
+            (200, &completion("Hello")),
+            (200, &completion("Hello")),
+        ]);
+        let client = builder
+            .with_retry_policy(RetryPolicy::never())
+            .build_blocking()
+            .unwrap();
+
+        let messages = vec![MessageRequest::user("Hi")];
+        let mut batch = client
+            .complete_batch((0..3).map(|_| CompletionsRequestBuilder::new(&messages)), 2)
+            .with_retry_policy(RetryPolicy::default().with_base_delay(Duration::ZERO))
+            .with_checkpoint(&path)
+            .unwrap();
+
+        let results: Vec<_> = (&mut batch).collect();
+        let indices: Vec<_> = results.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![0, 2]);
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(requests.lock().unwrap().len(), 3);
+        assert_eq!(batch.progress().skipped, 1);
+        assert_eq!(batch.usage().total_tokens, 4);
+        drop(batch);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 3);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_worker_panic_is_an_error() {
+        // The fake transport panics once it has no response left.
+        let (client, _) = fake_client(vec![(200, &completion("Hello"))]);
+        let messages = vec![MessageRequest::user("Hi")];
+        let results: Vec<_> = client
+            .complete_batch((0..3).map(|_| CompletionsRequestBuilder::new(&messages)), 1)
+            .with_retry_policy(RetryPolicy::never())
+            .collect();
+
+        let indices: Vec<_> = results.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+        assert!(results[0].1.is_ok());
+        assert!(matches!(results[1].1, Err(Error::Transport(_))));
+        assert!(matches!(results[2].1, Err(Error::Transport(_))));
+    }
+
+    /// The message of the request a `StallingTransport` holds back.
+    const STALL: &str = "Stall";
+
+    /// A `Transport` answering the request sending `STALL` once it is released, and the
+    /// others at once.
+    struct StallingTransport {
+        sent: Arc<AtomicUsize>,
+        release: Mutex<mpsc::Receiver<()>>,
+    }
+
+    impl Transport for StallingTransport {
+        fn send(&self, request: http::Request<Vec<u8>>) -> Result<Response<Body>, TransportError> {
+            self.sent.fetch_add(1, Ordering::SeqCst);
+            if String::from_utf8_lossy(request.body()).contains(STALL) {
+                let _ = self.release.lock().unwrap().recv();
+            }
+            let body: Body = Box::new(Cursor::new(completion("Hello")));
+            Ok(Response::builder().status(200).body(body).unwrap())
+        }
+    }
+
+    #[test]
+    fn test_batch_stops_behind_a_stalled_request() {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let (release, stalled) = mpsc::channel();
+        let client = DeepSeekClientBuilder::new("sk-test".to_string())
+            .with_host("http://fake")
+            .with_blocking_transport(StallingTransport {
+                sent: Arc::clone(&sent),
+                release: Mutex::new(stalled),
+            })
+            .build_blocking()
+            .unwrap();
+
+        let stalled = vec![MessageRequest::user(STALL)];
+        let messages = vec![MessageRequest::user("Hi")];
+        let requests = (0..100).map(|i| match i {
+            0 => CompletionsRequestBuilder::new(&stalled),
+            _ => CompletionsRequestBuilder::new(&messages),
+        });
+        let mut batch = client.complete_batch(requests, 2);
+        let sent_while_stalled = thread::scope(|scope| {
+            let watcher = scope.spawn(|| {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while sent.load(Ordering::SeqCst) < 2 * LOOKAHEAD && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                // Leave time for any request sent past the window.
+                thread::sleep(Duration::from_millis(50));
+                let sent = sent.load(Ordering::SeqCst);
+                release.send(()).unwrap();
+                sent
+            });
+            let (index, result) = batch.next().unwrap();
+            assert_eq!(index, 0);
+            assert!(result.is_ok());
+            watcher.join().unwrap()
+        });
+
+        assert_eq!(sent_while_stalled, 2 * LOOKAHEAD);
+    }
+}
@@ -0,0 +1,156 @@
+use super::transport::{Body, Transport};
+use crate::cassette::{Cassette, Mode, Recording};
+use crate::TransportError;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+/// A `Transport` recording the exchanges of `inner` to a cassette, or replaying them.
+pub(crate) struct CassetteTransport {
+    cassette: Arc<Cassette>,
+    inner: Arc<dyn Transport>,
+}
+
+impl CassetteTransport {
+    /// Opens the cassette at `path`; `inner` is only used in `Mode::Record`.
+    pub(crate) fn open(
+        path: &Path,
+        mode: Mode,
+        inner: Arc<dyn Transport>,
+    ) -> Result<Self, TransportError> {
+        Ok(CassetteTransport {
+            cassette: Arc::new(Cassette::open(path, mode)?),
+            inner,
+        })
+    }
+}
+
+impl Transport for CassetteTransport {
+    fn send(
+        &self,
+        request: http::Request<Vec<u8>>,
+    ) -> Result<http::Response<Body>, TransportError> {
+        if self.cassette.mode() == Mode::Replay {
+            let (parts, chunks) = self.cassette.replay(&request)?.into_parts();
+            let body: Body = Box::new(ChunkReader {
+                chunks: chunks.into(),
+                offset: 0,
+            });
+            return Ok(http::Response::from_parts(parts, body));
+        }
+
+        let mut recording = self.cassette.record(&request);
+        let (parts, body) = self.inner.send(request)?.into_parts();
+        recording.response(parts.status, &parts.headers);
+        let body: Body = Box::new(RecordingReader {
+            body,
+            recording: Some(recording),
+        });
+        Ok(http::Response::from_parts(parts, body))
+    }
+}
+
+/// A replayed body, returning at most one recorded chunk per read.
+struct ChunkReader {
+    chunks: VecDeque<Vec<u8>>,
+    offset: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(chunk) = self.chunks.front() else {
+            return Ok(0);
+        };
+        let n = buf.len().min(chunk.len() - self.offset);
+        buf[..n].copy_from_slice(&chunk[self.offset..self.offset + n]);
+        self.offset += n;
+        if self.offset == chunk.len() {
+            self.chunks.pop_front();
+            self.offset = 0;
+        }
+        Ok(n)
+    }
+}
+
+/// A body passing what it reads through to the client while recording it.
+struct RecordingReader {
+    body: Body,
+    recording: Option<Recording>,
+}
+
+impl Read for RecordingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.body.read(buf)?;
+        if n > 0 {
+            if let Some(recording) = &mut self.recording {
+                recording.push(&buf[..n]);
+            }
+        } else if !buf.is_empty() {
+            if let Some(recording) = self.recording.take() {
+                recording.finish().map_err(io::Error::other)?;
+            }
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cassette::{tests::temp_path, Mode};
+    use crate::request::MessageRequest;
+    use crate::sync_impl::fake::{completion, fake_builder};
+    use crate::{CompletionsRequestBuilder, DeepSeekClientBuilder, Error, RequestBuilder};
+
+    const STREAM: &str = "data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"deepseek-chat\",\"system_fingerprint\":\"fp\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":\"stop\"}]}\n\ndata: [DONE]\n\n";
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = temp_path("sync-cassette");
+        let messages = vec![MessageRequest::user("Hi")];
+
+        let (builder, requests) = fake_builder(vec![(200, &completion("Hello")), (200, STREAM)]);
+        let client = builder
+            .with_cassette(&path, Mode::Record)
+            .build_blocking()
+            .unwrap();
+        let recorded = CompletionsRequestBuilder::new(&messages)
+            .do_request(&client)
+            .unwrap()
+            .must_response();
+        let stream = CompletionsRequestBuilder::new(&messages)
+            .stream(true)
+            .do_request(&client)
+            .unwrap()
+            .must_stream();
+        for chunk in stream {
+            chunk.unwrap();
+        }
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        let client = DeepSeekClientBuilder::new("sk-other".to_string())
+            .with_cassette(&path, Mode::Replay)
+            .build_blocking()
+            .unwrap();
+        let completion = CompletionsRequestBuilder::new(&messages)
+            .stream(true)
+            .do_request(&client)
+            .unwrap()
+            .must_stream()
+            .collect_completion()
+            .unwrap();
+        assert_eq!(completion.choices[0].content(), "Hi");
+        let replayed = CompletionsRequestBuilder::new(&messages)
+            .do_request(&client)
+            .unwrap()
+            .must_response();
+        assert_eq!(replayed.choices[0].content(), recorded.choices[0].content());
+
+        let err = CompletionsRequestBuilder::new(&messages)
+            .do_request(&client)
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Transport(_)));
+        std::fs::remove_file(path).unwrap();
+    }
+}
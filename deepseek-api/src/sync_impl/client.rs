@@ -1,11 +1,17 @@
 use super::error::ToApiError;
 use super::json_stream::JsonStream;
+use super::transport::{read_body, Body, Transport};
+use crate::rate_limit::{estimate_tokens, Permit, RateLimiter};
 use crate::{
-    response::{BalanceResp, ChatResponse, ModelResp},
-    RequestBuilder,
+    response::{self, BalanceResp, ModelResp},
+    CompletionClient, Error, PreparedRequest, RequestBuilder, Result, RetryPolicy, ValidationError,
 };
-use anyhow::Result;
-use reqwest::blocking::Client as ReqwestClient;
+use http::{header::CONTENT_TYPE, HeaderMap, HeaderValue, Method, Response};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+/// A [`response::ChatResponse`] whose stream is the blocking [`JsonStream`].
+pub type ChatResponse<RESP, ITEM> = response::ChatResponse<RESP, JsonStream<ITEM>>;
 
 #[derive(Clone)]
 /// A client for interacting with the DeepSeek API.
@@ -16,7 +22,7 @@ use reqwest::blocking::Client as ReqwestClient;
 ///     use deepseek_api::DeepSeekClientBuilder;
 ///
 ///     let api_key = "your_api_key".to_string();
-///     let client = DeepSeekClientBuilder::new(api_key).build().unwrap();
+///     let client = DeepSeekClientBuilder::new(api_key).build_blocking().unwrap();
 ///
 ///     // Get available models
 ///     let models = client.models().unwrap();
@@ -27,11 +33,17 @@ use reqwest::blocking::Client as ReqwestClient;
 ///
 /// # Fields
 ///
-/// * `client` - The underlying HTTP client.
+/// * `transport` - The transport sending HTTP requests, `ReqwestTransport` by default.
 /// * `host` - The base URL for the DeepSeek API.
+/// * `headers` - Headers sent with every request, including `Authorization`.
+/// * `retry_policy` - How transient failures are retried.
+/// * `rate_limiter` - The `RateLimit` shared with the clones of the client, if any.
 pub struct DeepSeekClient {
-    pub(crate) client: ReqwestClient,
+    pub(crate) transport: Arc<dyn Transport>,
     pub(crate) host: String,
+    pub(crate) headers: HeaderMap,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) rate_limiter: Option<Arc<RateLimiter>>,
 }
 
 impl DeepSeekClient {
@@ -51,18 +63,15 @@ impl DeepSeekClient {
     ///     use deepseek_api::DeepSeekClientBuilder;
     ///
     ///     let api_key = "your_api_key".to_string();
-    ///     let client = DeepSeekClientBuilder::new(api_key).build().unwrap();
+    ///     let client = DeepSeekClientBuilder::new(api_key).build_blocking().unwrap();
     ///     let models = client.models().unwrap();
     ///     println!("{:?}", models);
     /// ```
     ///
     /// For more information, see the [DeepSeek API documentation](https://api-docs.deepseek.com/zh-cn/api/list-models).
     pub fn models(&self) -> Result<ModelResp> {
-        Ok(self
-            .client
-            .get(self.host.to_owned() + "/models")
-            .send()?
-            .json()?)
+        let resp = self.send_with_retry(Method::GET, "/models", None, &HeaderMap::new())?;
+        decode_json(resp)
     }
 
     /// Retrieves the balance information of the user from the DeepSeek API.
@@ -81,18 +90,15 @@ impl DeepSeekClient {
     ///     use deepseek_api::DeepSeekClientBuilder;
     ///
     ///     let api_key = "your_api_key".to_string();
-    ///     let client = DeepSeekClientBuilder::new(api_key).build().unwrap();
+    ///     let client = DeepSeekClientBuilder::new(api_key).build_blocking().unwrap();
     ///     let balance = client.balance().unwrap();
     ///     println!("{:?}", balance);
     /// ```
     ///
     /// For more information, see the [DeepSeek API documentation](https://api-docs.deepseek.com/zh-cn/api/get-user-balance).
     pub fn balance(&self) -> Result<BalanceResp> {
-        Ok(self
-            .client
-            .get(self.host.to_owned() + "/user/balance")
-            .send()?
-            .json()?)
+        let resp = self.send_with_retry(Method::GET, "/user/balance", None, &HeaderMap::new())?;
+        decode_json(resp)
     }
 
     /// Sends a completion request to the DeepSeek API.
@@ -121,19 +127,19 @@ impl DeepSeekClient {
     /// # Errors
     ///
     /// This function will return an error if:
-    /// - The request fails to send.
-    /// - The response contains an API error.
-    /// - The response cannot be deserialized into the expected type.
+    /// - The request is invalid for the chosen model (`Error::InvalidRequest`).
+    /// - The request fails to send (`Error::Transport`).
+    /// - The response contains an API error (`Error::Api`).
+    /// - The response cannot be deserialized into the expected type (`Error::Deserialize`).
     ///
     /// # Example
     ///
     /// ```no_run
     ///     use deepseek_api::{request::MessageRequest, DeepSeekClientBuilder, CompletionsRequestBuilder};
-    ///     use deepseek_api::response::ChatResponse;
-    ///     use futures_util::StreamExt;
+    ///     use deepseek_api::blocking::ChatResponse;
     ///
     ///     let api_key = "your_api_key".to_string();
-    ///     let client = DeepSeekClientBuilder::new(api_key).build().unwrap();
+    ///     let client = DeepSeekClientBuilder::new(api_key).build_blocking().unwrap();
     ///     let msgs = &[MessageRequest::user("Hello, DeepSeek!")];
     ///     let request_builder = CompletionsRequestBuilder::new(msgs);
     ///
@@ -156,25 +162,209 @@ impl DeepSeekClient {
     where
         Builder: RequestBuilder + Send + Sized,
     {
-        let host = if request_builder.is_beta() {
-            self.host.to_owned() + "/beta/completions"
-        } else {
-            self.host.to_owned() + "/chat/completions"
-        };
-        let is_stream = request_builder.is_stream();
-
-        let request = request_builder.build();
-        let resp = self
-            .client
-            .post(&host)
-            .json(&request)
-            .send()?
-            .to_api_err()?;
-
-        if is_stream {
-            Ok(ChatResponse::Stream(JsonStream::new(resp)))
+        self.send_prepared(PreparedRequest::new(request_builder)?)
+    }
+
+    /// Sends a request prepared by `PreparedRequest::new`.
+    pub(crate) fn send_prepared<RESP, ITEM>(
+        &self,
+        prepared: PreparedRequest,
+    ) -> Result<ChatResponse<RESP, ITEM>>
+    where
+        RESP: DeserializeOwned,
+        ITEM: DeserializeOwned,
+    {
+        let mut resp = self.send_with_retry(
+            Method::POST,
+            prepared.path,
+            Some(prepared.body),
+            &prepared.headers,
+        )?;
+        if prepared.is_stream {
+            let permit = resp.extensions_mut().remove::<Permit>();
+            Ok(ChatResponse::Stream(
+                JsonStream::new(resp.into_body()).with_permit(permit),
+            ))
         } else {
-            Ok(ChatResponse::Full(resp.json()?))
+            Ok(ChatResponse::Full(decode_json(resp)?))
         }
     }
+
+    /// Sends the request, retrying transient failures according to the client's `RetryPolicy`.
+    ///
+    /// Only the status line and headers are inspected before deciding to retry, so once a
+    /// successful response is returned its body (streaming or not) is never requested again.
+    /// `headers` are sent on top of the client's headers. Each attempt waits for the rate
+    /// limiter, and a successful response carries its `Permit` in its extensions.
+    fn send_with_retry(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+        headers: &HeaderMap,
+    ) -> Result<Response<Body>> {
+        let tokens = body.as_deref().map_or(0, estimate_tokens);
+        let mut attempt = 1;
+        loop {
+            let req = self.request(method.clone(), path, body.clone(), headers)?;
+            let permit = self
+                .rate_limiter
+                .as_ref()
+                .map(|limiter| limiter.acquire_blocking(tokens));
+            let (err, retry_after): (Error, _) = match self.transport.send(req) {
+                Ok(resp) => match resp.to_api_err() {
+                    Ok(mut resp) => {
+                        if let Some(permit) = permit {
+                            resp.extensions_mut().insert(permit);
+                        }
+                        return Ok(resp);
+                    }
+                    Err(err) if err.is_retryable() => {
+                        let retry_after = err.retry_after();
+                        (err.into(), retry_after)
+                    }
+                    Err(err) => return Err(err.into()),
+                },
+                Err(err) if err.is_connect() => (err.into(), None),
+                Err(err) => return Err(err.into()),
+            };
+
+            if !self.retry_policy.should_retry(attempt) {
+                return Err(err);
+            }
+            std::thread::sleep(self.retry_policy.delay_for(attempt, retry_after));
+            attempt += 1;
+        }
+    }
+
+    fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+        extra_headers: &HeaderMap,
+    ) -> Result<http::Request<Vec<u8>>> {
+        let url = self.host.to_owned() + path;
+        let mut builder = http::Request::builder().method(method).uri(&url);
+        if let Some(headers) = builder.headers_mut() {
+            headers.extend(self.headers.clone());
+            if body.is_some() {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            }
+            headers.extend(extra_headers.clone());
+        }
+        builder
+            .body(body.unwrap_or_default())
+            .map_err(|_| ValidationError::new("host", "a valid URL", url).into())
+    }
+}
+
+impl CompletionClient for DeepSeekClient {
+    type Output<'a, B: RequestBuilder + 'a> = Result<ChatResponse<B::Response, B::Item>>;
+
+    fn send_request<'a, B: RequestBuilder + 'a>(
+        &'a self,
+        request_builder: B,
+    ) -> Self::Output<'a, B> {
+        self.send_completion_request(request_builder)
+    }
+}
+
+fn decode_json<T: DeserializeOwned>(mut resp: Response<Body>) -> Result<T> {
+    let permit = resp.extensions_mut().remove::<Permit>();
+    let body = read_body(resp.into_body())?;
+    if let Some(permit) = permit {
+        permit.record_body_usage(&body);
+    }
+    Ok(serde_json::from_slice(&body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::MessageRequest;
+    use crate::sync_impl::fake::fake_builder;
+    use crate::sync_impl::fake::fake_client;
+    use crate::{ApiError, CompletionsRequestBuilder, FMICompletionsRequestBuilder};
+
+    #[test]
+    fn test_retries_through_transport() {
+        let (client, requests) = fake_client(vec![
+            (503, "unavailable"),
+            (200, r#"{"is_available":true,"balance_infos":[]}"#),
+        ]);
+
+        let balance = client.balance().unwrap();
+        assert!(balance.is_available);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].method(), Method::GET);
+        assert_eq!(requests[1].uri(), "http://fake/user/balance");
+        assert_eq!(requests[1].headers()["authorization"], "Bearer sk-test");
+    }
+
+    #[test]
+    fn test_client_errors_are_not_retried() {
+        let (client, requests) = fake_client(vec![(401, "bad key")]);
+
+        let err = client.models().unwrap_err();
+        assert!(matches!(err, Error::Api(ApiError::Unauthorized(_))));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_extra_body_and_headers() {
+        let completion = r#"{"id":"c1","object":"text_completion","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[{"index":0,"finish_reason":"stop","text":"}"}],"usage":{"completion_tokens":1,"prompt_tokens":1,"prompt_cache_hit_tokens":0,"prompt_cache_miss_tokens":1,"total_tokens":2}}"#;
+        let (client, requests) = fake_client(vec![(200, completion)]);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-trace-id", HeaderValue::from_static("t-1"));
+        FMICompletionsRequestBuilder::new("fn main() {", "")
+            .extra_body(serde_json::json!({"new_parameter": true}))
+            .unwrap()
+            .extra_headers(headers)
+            .do_request(&client)
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].headers()["x-trace-id"], "t-1");
+        assert_eq!(requests[0].headers()["authorization"], "Bearer sk-test");
+        let body: serde_json::Value = serde_json::from_slice(requests[0].body()).unwrap();
+        assert_eq!(body["new_parameter"], true);
+        assert_eq!(body["prompt"], "fn main() {");
+    }
+
+    #[test]
+    fn test_rate_limiter_tracks_usage() {
+        let completion = r#"{"id":"c1","object":"chat.completion","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[{"index":0,"finish_reason":"stop","message":{"role":"assistant","content":"Hi"}}],"usage":{"completion_tokens":1,"prompt_tokens":2,"prompt_cache_hit_tokens":0,"prompt_cache_miss_tokens":2,"total_tokens":3}}"#;
+        let stream = [
+            r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":"stop"}]}"#,
+            r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[],"usage":{"completion_tokens":1,"prompt_tokens":6,"prompt_cache_hit_tokens":0,"prompt_cache_miss_tokens":6,"total_tokens":7}}"#,
+            "data: [DONE]",
+            "",
+        ]
+        .join("\n\n");
+        let (builder, _) = fake_builder(vec![(200, completion), (200, &stream)]);
+        let client = builder
+            .with_rate_limit(crate::RateLimit::default().with_max_in_flight(1))
+            .build_blocking()
+            .unwrap();
+        let limiter = client.clone().rate_limiter.unwrap();
+        let messages = vec![MessageRequest::user("Hi")];
+
+        CompletionsRequestBuilder::new(&messages)
+            .do_request(&client)
+            .unwrap();
+        assert_eq!(limiter.snapshot(), (0, vec![3]));
+
+        let mut stream = CompletionsRequestBuilder::new(&messages)
+            .stream(true)
+            .do_request(&client)
+            .unwrap()
+            .must_stream();
+        assert_eq!(limiter.snapshot().0, 1);
+        for _ in stream.by_ref() {}
+        assert_eq!(limiter.snapshot(), (0, vec![3, 7]));
+    }
 }
@@ -0,0 +1,247 @@
+use super::client::DeepSeekClient;
+use super::json_stream::JsonStream;
+use crate::{
+    response::{
+        ChatCompletion, ChatCompletionStream, CompletionAccumulator, JSONChoiceStream, Usage,
+    },
+    Conversation, Error, RequestBuilder, Result, StreamError,
+};
+
+impl Conversation {
+    /// Sends the conversation and appends the reply of the model to it.
+    ///
+    /// # Returns
+    ///
+    /// The completion whose first choice was appended.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the request; the conversation is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// conversation.push(MessageRequest::user("Hello!"));
+    /// let completion = conversation.send_blocking(&client)?;
+    /// ```
+    pub fn send_blocking(&mut self, client: &DeepSeekClient) -> Result<ChatCompletion> {
+        let mut builder = self.request_builder().stream(false);
+        builder.stream_options = None;
+        let completion = builder.do_request(client)?.must_response();
+        self.push_completion(&completion);
+        Ok(completion)
+    }
+
+    /// Sends the conversation as a streaming request.
+    ///
+    /// The returned iterator yields the chunks as they arrive and appends the assembled reply
+    /// to the conversation once the server ends the stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the request; the conversation is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// conversation.push(MessageRequest::user("Hello!"));
+    /// for chunk in conversation.send_stream_blocking(&client)? {
+    ///     print!("{:?}", chunk?.choices[0].delta.content);
+    /// }
+    /// ```
+    pub fn send_stream_blocking(
+        &mut self,
+        client: &DeepSeekClient,
+    ) -> Result<ConversationStream<'_>> {
+        let stream = self
+            .request_builder()
+            .stream(true)
+            .do_request(client)?
+            .must_stream();
+        Ok(ConversationStream {
+            conversation: self,
+            stream,
+            accumulator: CompletionAccumulator::new(),
+            completion: None,
+            failed: false,
+            done: false,
+        })
+    }
+}
+
+/// The iterator returned by `Conversation::send_stream_blocking`.
+///
+/// It yields the chunks of the reply and, when the server ends the stream, appends the
+/// assembled message to the conversation. Nothing is appended if the stream yields an error
+/// or is dropped before its end.
+pub struct ConversationStream<'c> {
+    conversation: &'c mut Conversation,
+    stream: JsonStream<ChatCompletionStream<JSONChoiceStream>>,
+    accumulator: CompletionAccumulator,
+    completion: Option<ChatCompletion>,
+    failed: bool,
+    done: bool,
+}
+
+impl ConversationStream<'_> {
+    /// Returns the usage sent by the server, once the stream has been read up to it.
+    ///
+    /// It is only sent when the conversation sets `StreamOptions::new(true)`.
+    pub fn usage(&self) -> Option<&Usage> {
+        self.stream.usage()
+    }
+
+    /// Returns the assembled completion, once the stream has ended.
+    pub fn completion(&self) -> Option<&ChatCompletion> {
+        self.completion.as_ref()
+    }
+
+    /// Reads the rest of the stream and returns the assembled completion.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error yielded by the stream, or `Error::Stream` if that error was
+    /// already read from the stream.
+    pub fn collect_completion(mut self) -> Result<ChatCompletion> {
+        for chunk in self.by_ref() {
+            chunk?;
+        }
+        self.completion.ok_or_else(|| {
+            StreamError::Incomplete("the stream yielded an error before its end".to_string()).into()
+        })
+    }
+}
+
+impl Iterator for ConversationStream<'_> {
+    type Item = Result<ChatCompletionStream<JSONChoiceStream>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.stream.next() {
+            Some(Ok(chunk)) => {
+                self.accumulator.push(chunk.clone());
+                Some(Ok(chunk))
+            }
+            Some(Err(err)) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+            None => {
+                self.done = true;
+                if self.failed {
+                    return None;
+                }
+                match std::mem::take(&mut self.accumulator).finish() {
+                    Ok(completion) => {
+                        self.conversation.push_completion(&completion);
+                        self.completion = Some(completion);
+                        None
+                    }
+                    Err(err) => Some(Err(err.into())),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::{MessageRequest, StreamOptions};
+    use crate::sync_impl::fake::{completion, fake_client};
+
+    fn chunk(content: &str, finish_reason: &str) -> String {
+        format!(
+            "data: {{\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"deepseek-chat\",\"system_fingerprint\":\"fp\",\"choices\":[{{\"index\":0,\"delta\":{{\"content\":\"{}\"}},\"finish_reason\":{}}}]}}\n\n",
+            content, finish_reason
+        )
+    }
+
+    #[test]
+    fn test_send_appends_reply() {
+        let (client, requests) = fake_client(vec![(200, &completion("Hello"))]);
+        let mut conversation = Conversation::new().with_system("Be brief.");
+        conversation.push(MessageRequest::user("Hi"));
+
+        conversation.send_blocking(&client).unwrap();
+
+        assert_eq!(conversation.len(), 2);
+        assert_eq!(conversation.messages()[2].get_content(), "Hello");
+        let requests = requests.lock().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(requests[0].body()).unwrap();
+        assert_eq!(body["messages"].as_array().unwrap().len(), 2);
+        assert_eq!(body["stream"], false);
+    }
+
+    #[test]
+    fn test_send_stream_appends_reply() {
+        let body = chunk("Hel", "null") + &chunk("lo", "\"stop\"") + "data: [DONE]\n\n";
+        let (client, _) = fake_client(vec![(200, &body)]);
+        let mut conversation = Conversation::new();
+        conversation.push(MessageRequest::user("Hi"));
+
+        let stream = conversation.send_stream_blocking(&client).unwrap();
+        let completion = stream.collect_completion().unwrap();
+
+        assert_eq!(
+            completion.choices[0].message.as_ref().unwrap().content,
+            "Hello"
+        );
+        assert_eq!(conversation.len(), 2);
+        assert_eq!(conversation.messages()[1].get_content(), "Hello");
+    }
+
+    #[test]
+    fn test_send_stream_incomplete() {
+        let body = chunk("Hel", "null");
+        let (client, _) = fake_client(vec![(200, &body)]);
+        let mut conversation = Conversation::new();
+        conversation.push(MessageRequest::user("Hi"));
+
+        let stream = conversation.send_stream_blocking(&client).unwrap();
+        let err = stream.collect_completion().unwrap_err();
+
+        assert!(matches!(err, Error::Stream(StreamError::Incomplete(_))));
+        assert_eq!(conversation.len(), 1);
+    }
+
+    #[test]
+    fn test_collect_after_reading_an_error() {
+        let body = chunk("Hel", "null") + "data: {not json\n\n";
+        let (client, _) = fake_client(vec![(200, &body)]);
+        let mut conversation = Conversation::new();
+        conversation.push(MessageRequest::user("Hi"));
+
+        let mut stream = conversation.send_stream_blocking(&client).unwrap();
+        assert!(stream.next().unwrap().is_ok());
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+        let err = stream.collect_completion().unwrap_err();
+
+        assert!(matches!(err, Error::Stream(StreamError::Incomplete(_))));
+        assert_eq!(conversation.len(), 1);
+    }
+
+    #[test]
+    fn test_stream_options_include_usage() {
+        let body = chunk("Hello", "\"stop\"") + "data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"deepseek-chat\",\"system_fingerprint\":\"fp\",\"choices\":[],\"usage\":{\"completion_tokens\":1,\"prompt_tokens\":1,\"prompt_cache_hit_tokens\":0,\"prompt_cache_miss_tokens\":1,\"total_tokens\":2}}\n\n" + "data: [DONE]\n\n";
+        let (client, requests) = fake_client(vec![(200, &completion("Hello")), (200, &body)]);
+        let mut conversation = Conversation::new().stream_options(StreamOptions::new(true));
+        conversation.push(MessageRequest::user("Hi"));
+
+        conversation.send_blocking(&client).unwrap();
+        let mut stream = conversation.send_stream_blocking(&client).unwrap();
+        stream.by_ref().for_each(drop);
+        assert_eq!(stream.usage().unwrap().total_tokens, 2);
+        drop(stream);
+
+        let requests = requests.lock().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(requests[0].body()).unwrap();
+        assert!(body.get("stream_options").is_none());
+        let body: serde_json::Value = serde_json::from_slice(requests[1].body()).unwrap();
+        assert_eq!(body["stream_options"]["include_usage"], true);
+    }
+}
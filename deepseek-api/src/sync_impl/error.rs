@@ -1,33 +1,117 @@
-use crate::error::ApiError;
-use reqwest::blocking::Response;
+use super::transport::{read_body, Body};
+use crate::error::{ApiError, ErrorResponse};
+use http::Response;
 
-pub trait ToApiError {
-    fn to_api_err(self) -> Result<Response, ApiError>;
+pub trait ToApiError: Sized {
+    fn to_api_err(self) -> Result<Self, ApiError>;
 }
 
-impl ToApiError for Response {
-    fn to_api_err(self) -> Result<Response, ApiError> {
-        let status = self.status().as_u16();
-        match status {
-            400 | 401 | 402 | 422 | 429 | 500 | 503 => {
-                let message = self
-                    .text()
-                    .map_err(|err| ApiError::Unknown(err.to_string()))?;
-                Err(match status {
-                    400 => ApiError::BadRequest(message),
-                    401 => ApiError::Unauthorized(message),
-                    402 => ApiError::InsufficientFunds(message),
-                    422 => ApiError::InvalidParameters(message),
-                    429 => ApiError::RateLimitExceeded(message),
-                    500 => ApiError::ServerError(message),
-                    503 => ApiError::ServiceUnavailable(message),
-                    code => ApiError::Unknown(format!(
-                        "Response error not in document{}: {}",
-                        code, message
-                    )),
-                })
-            }
-            _ => Ok(self),
+impl ToApiError for Response<Body> {
+    fn to_api_err(self) -> Result<Self, ApiError> {
+        let status = self.status();
+        if status.is_success() {
+            return Ok(self);
         }
+
+        let status = status.as_u16();
+        let (parts, body) = self.into_parts();
+        let resp = match read_body(body) {
+            Ok(body) => ErrorResponse::new(
+                status,
+                &parts.headers,
+                String::from_utf8_lossy(&body).into_owned(),
+            ),
+            Err(err) => ErrorResponse {
+                message: err.to_string(),
+                ..ErrorResponse::new(status, &parts.headers, String::new())
+            },
+        };
+        Err(resp.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{response::Builder, StatusCode};
+    use std::error::Error;
+    use std::io::Cursor;
+
+    fn body(body: &str) -> Body {
+        Box::new(Cursor::new(body.to_owned()))
+    }
+
+    fn mock_response(status: u16, text: &str) -> Response<Body> {
+        Builder::new().status(status).body(body(text)).unwrap()
+    }
+
+    #[test]
+    fn test_ok_responses() -> Result<(), Box<dyn Error>> {
+        let ok_statuses = [200, 201, 204];
+
+        for status in ok_statuses {
+            let resp = mock_response(status, "");
+            let result = resp.to_api_err()?;
+            assert_eq!(result.status(), StatusCode::from_u16(status)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_mapping() {
+        type Variant = fn(Box<ErrorResponse>) -> ApiError;
+        let test_cases: Vec<(u16, Variant)> = vec![
+            (400, ApiError::BadRequest),
+            (401, ApiError::Unauthorized),
+            (402, ApiError::InsufficientFunds),
+            (403, ApiError::Forbidden),
+            (404, ApiError::NotFound),
+            (408, ApiError::RequestTimeout),
+            (422, ApiError::InvalidParameters),
+            (429, ApiError::RateLimitExceeded),
+            (500, ApiError::ServerError),
+            (502, ApiError::BadGateway),
+            (503, ApiError::ServiceUnavailable),
+            (504, ApiError::GatewayTimeout),
+            (302, ApiError::Unknown),
+            (418, ApiError::Unknown),
+        ];
+
+        for (status, variant) in test_cases {
+            let body = format!(
+                r#"{{"error":{{"message":"failed with {}","type":"test_error","code":null}}}}"#,
+                status
+            );
+            let resp = mock_response(status, &body);
+            let err = resp.to_api_err().err().unwrap();
+
+            let expected = variant(Box::new(ErrorResponse {
+                status,
+                message: format!("failed with {}", status),
+                error_type: Some("test_error".to_string()),
+                body,
+                ..Default::default()
+            }));
+            assert_eq!(
+                err, expected,
+                "Status {} generated wrong error type: {:?}",
+                status, err
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_headers() {
+        let response = Builder::new()
+            .status(429)
+            .header("retry-after", "3")
+            .header("x-request-id", "abc")
+            .body(body("slow down"))
+            .unwrap();
+        let err = response.to_api_err().err().unwrap();
+
+        assert_eq!(err.message(), "slow down");
+        assert_eq!(err.request_id(), Some("abc"));
+        assert_eq!(err.retry_after(), Some(std::time::Duration::from_secs(3)));
     }
 }
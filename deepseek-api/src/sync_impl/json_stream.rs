@@ -1,23 +1,98 @@
-use anyhow::{anyhow, Error};
-use reqwest::blocking::Response;
-use serde::de::DeserializeOwned;
-use std::{
-    io::{BufRead, BufReader},
-    marker::PhantomData,
+use super::transport::Body;
+use crate::rate_limit::Permit;
+use crate::response::{
+    ChatCompletion, ChatCompletionStream, CompletionAccumulator, StreamChoice, Usage,
 };
+use crate::sse::{decode_event, event_usage, Event, EventParser};
+use crate::Error;
+use serde::de::DeserializeOwned;
+use std::{collections::VecDeque, io::Read, marker::PhantomData};
+
+/// The size of the buffer the response body is read into.
+const CHUNK_SIZE: usize = 8 * 1024;
 
+/// An iterator that parses a blocking HTTP response body as a `text/event-stream` and
+/// deserializes the data of each event as a JSON object.
+///
+/// The iterator terminates when it receives a `[DONE]` event. It yields `Error::Stream` if
+/// the JSON deserialization fails, `Error::Api` if the server sends an error event or an
+/// `{"error": {...}}` payload, and `Error::Transport` if the body cannot be read.
 pub struct JsonStream<T> {
-    _ph: PhantomData<T>,
-    lines: std::io::Lines<BufReader<Response>>,
+    _ph: PhantomData<fn() -> T>,
+    body: Body,
+    parser: EventParser,
+    events: VecDeque<Event>,
+    usage: Option<Usage>,
+    permit: Option<Permit>,
+    buf: Vec<u8>,
+    done: bool,
 }
 
 impl<T: DeserializeOwned> JsonStream<T> {
-    pub fn new(response: Response) -> Self {
-        let lines = BufReader::new(response).lines();
+    pub fn new(body: Body) -> Self {
         JsonStream {
             _ph: PhantomData,
-            lines,
+            body,
+            parser: EventParser::default(),
+            events: VecDeque::new(),
+            usage: None,
+            permit: None,
+            buf: vec![0; CHUNK_SIZE],
+            done: false,
+        }
+    }
+
+    /// Holds the rate limiter permit of the request until the stream ends or is dropped,
+    /// and corrects its tokens with the usage sent by the server.
+    pub(crate) fn with_permit(mut self, permit: Option<Permit>) -> Self {
+        self.permit = permit;
+        self
+    }
+}
+
+impl<T: StreamChoice + DeserializeOwned> JsonStream<ChatCompletionStream<T>> {
+    /// Returns the usage sent by the server, once the stream has been read up to it.
+    ///
+    /// The usage is only sent when the request sets `StreamOptions { include_usage: true }`,
+    /// in a final chunk with no choices, so this returns `None` until that chunk is read.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// for chunk in stream.by_ref() {
+    ///     println!("{:?}", chunk?);
+    /// }
+    /// println!("{:?}", stream.usage());
+    /// ```
+    pub fn usage(&self) -> Option<&Usage> {
+        self.usage.as_ref()
+    }
+
+    /// Consumes the stream and folds its chunks into a `ChatCompletion`.
+    ///
+    /// The result has the same shape as a non-streaming response; see
+    /// `CompletionAccumulator` for how the chunks are assembled.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error yielded by the stream, or `StreamError::Incomplete` if the
+    /// stream ended before every choice finished.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let completion = CompletionsRequestBuilder::new(&messages)
+    ///     .stream(true)
+    ///     .do_request(&client)?
+    ///     .must_stream()
+    ///     .collect_completion()?;
+    /// ```
+    pub fn collect_completion(self) -> Result<ChatCompletion, Error> {
+        let mut accumulator = CompletionAccumulator::new();
+        for chunk in self {
+            accumulator.push(chunk?);
         }
+        Ok(accumulator.finish()?)
     }
 }
 
@@ -25,39 +100,43 @@ impl<T: DeserializeOwned> Iterator for JsonStream<T> {
     type Item = Result<T, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        for line_result in &mut self.lines {
-            match line_result {
-                Ok(line) => {
-                    let line = line.trim();
-                    if line == "data: [DONE]" {
-                        return None;
-                    }
-                    if line.is_empty() || line == ": keep-alive" {
-                        continue;
-                    }
-                    if let Some(json_str) = line.strip_prefix("data: ") {
-                        match serde_json::from_str::<T>(json_str) {
-                            Ok(value) => return Some(Ok(value)),
-                            Err(err) => {
-                                return Some(Err(anyhow!("jsonstr: {} reason {}", json_str, err)))
-                            }
-                        }
-                    } else {
-                        return Some(Err(anyhow!("{} Missing 'data: ' prefix", line)));
+        loop {
+            if self.done {
+                self.permit = None;
+                return None;
+            }
+            if let Some(event) = self.events.pop_front() {
+                if let Some(usage) = event_usage(&event) {
+                    if let Some(permit) = &self.permit {
+                        permit.record_usage(&usage);
                     }
+                    self.usage = Some(usage);
+                }
+                match decode_event(event) {
+                    Ok(Some(item)) => return Some(Ok(item)),
+                    Ok(None) => self.done = true,
+                    Err(err) => return Some(Err(err)),
+                }
+                continue;
+            }
+
+            match self.body.read(&mut self.buf) {
+                Ok(0) => self.done = true,
+                Ok(n) => self.parser.feed(&self.buf[..n], &mut self.events),
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err.into()));
                 }
-                Err(e) => return Some(Err(Error::new(e))),
             }
         }
-        None
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use http::Response;
-    use reqwest::blocking::Response as ReqwestResponse;
+    use crate::StreamError;
     use serde::Deserialize;
 
     #[derive(Debug, Deserialize, PartialEq)]
@@ -66,19 +145,14 @@ mod tests {
         value: String,
     }
 
-    fn mock_response(body: &str) -> ReqwestResponse {
-        let http_response = Response::builder()
-            .body(body.to_owned().into_bytes())
-            .unwrap();
-        ReqwestResponse::from(http_response)
+    fn mock_response(body: &str) -> Body {
+        Box::new(std::io::Cursor::new(body.to_owned().into_bytes()))
     }
 
     #[test]
     fn test_normal_stream() {
         let response = mock_response(
-            r#"data: {"id":1,"value":"test1"}
-            data: {"id":2,"value":"test2"}
-            data: [DONE]"#,
+            "data: {\"id\":1,\"value\":\"test1\"}\n\ndata: {\"id\":2,\"value\":\"test2\"}\n\ndata: [DONE]\n\n",
         );
 
         let mut stream = JsonStream::<TestData>::new(response);
@@ -105,31 +179,26 @@ mod tests {
 
     #[test]
     fn test_invalid_json() {
-        let response = mock_response(r#"data: {invalid_json}"#);
+        let response = mock_response("data: {invalid_json}\n\n");
         let mut stream = JsonStream::<TestData>::new(response);
 
         let err = stream.next().unwrap().unwrap_err();
-        assert!(err.to_string().contains("reason"));
+        assert!(err.to_string().contains("invalid JSON in stream event"));
         assert!(err.to_string().contains("invalid_json"));
     }
 
     #[test]
-    fn test_missing_prefix() {
-        let response = mock_response(r#"{"id":3,"value":"error"}"#);
+    fn test_unknown_lines_are_ignored() {
+        let response = mock_response("{\"id\":3,\"value\":\"error\"}\n\n");
         let mut stream = JsonStream::<TestData>::new(response);
 
-        let err = stream.next().unwrap().unwrap_err();
-        assert!(err.to_string().contains("Missing 'data: ' prefix"));
+        assert!(stream.next().is_none());
     }
 
     #[test]
     fn test_skip_empty_lines() {
         let response = mock_response(
-            r#"
-            : keep-alive
-            
-            data: {"id":4,"value":"empty"}
-            data: [DONE]"#,
+            "\u{FEFF}\n: keep-alive\n\ndata: {\"id\":4,\"value\":\"empty\"}\r\rdata: [DONE]\r\r",
         );
 
         let mut stream = JsonStream::<TestData>::new(response);
@@ -137,4 +206,102 @@ mod tests {
         assert_eq!(item.id, 4);
         assert!(stream.next().is_none());
     }
+
+    #[test]
+    fn test_error_event() {
+        let response = mock_response("event: error\ndata: {\"error\":{\"message\":\"boom\"}}\n\n");
+        let mut stream = JsonStream::<TestData>::new(response);
+
+        let err = stream.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "Server Error (500): boom");
+    }
+
+    #[test]
+    fn test_collect_chat_completion() {
+        let response = mock_response(concat!(
+            r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"deepseek-reasoner","system_fingerprint":"fp","choices":[{"index":0,"delta":{"role":"assistant","content":null,"reasoning_content":"Think"},"finish_reason":null}]}"#,
+            "\n\n",
+            r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"deepseek-reasoner","system_fingerprint":"fp","choices":[{"index":0,"delta":{"content":"Hel","reasoning_content":null},"finish_reason":null}]}"#,
+            "\n\n",
+            r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"deepseek-reasoner","system_fingerprint":"fp","choices":[{"index":0,"delta":{"content":"lo"},"finish_reason":"stop"}]}"#,
+            "\n\n",
+            r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"deepseek-reasoner","system_fingerprint":"fp","choices":[],"usage":{"completion_tokens":3,"prompt_tokens":2,"prompt_cache_hit_tokens":0,"prompt_cache_miss_tokens":2,"total_tokens":5}}"#,
+            "\n\n",
+            "data: [DONE]\n",
+        ));
+
+        let completion =
+            JsonStream::<ChatCompletionStream<crate::response::JSONChoiceStream>>::new(response)
+                .collect_completion()
+                .unwrap();
+
+        assert_eq!(completion.id, "c1");
+        assert_eq!(completion.object, "chat.completion");
+        assert_eq!(completion.usage.total_tokens, 5);
+        assert_eq!(completion.choices.len(), 1);
+        let choice = &completion.choices[0];
+        assert_eq!(choice.finish_reason, crate::response::FinishReason::Stop);
+        let message = choice.message.as_ref().unwrap();
+        assert_eq!(message.content, "Hello");
+        assert_eq!(message.reasoning_content.as_deref(), Some("Think"));
+        assert!(message.tool_calls.is_none());
+    }
+
+    #[test]
+    fn test_collect_text_completion() {
+        let response = mock_response(concat!(
+            r#"data: {"id":"t1","object":"text_completion","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[{"index":0,"text":"fn ","finish_reason":null}]}"#,
+            "\n\n",
+            r#"data: {"id":"t1","object":"text_completion","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[{"index":0,"text":"main()","finish_reason":"length"}]}"#,
+            "\n\n",
+            "data: [DONE]\n",
+        ));
+
+        let completion =
+            JsonStream::<ChatCompletionStream<crate::response::TextChoiceStream>>::new(response)
+                .collect_completion()
+                .unwrap();
+
+        assert_eq!(completion.object, "text_completion");
+        let choice = &completion.choices[0];
+        assert_eq!(choice.text.as_deref(), Some("fn main()"));
+        assert!(choice.message.is_none());
+        assert_eq!(choice.finish_reason, crate::response::FinishReason::Length);
+    }
+
+    #[test]
+    fn test_collect_unfinished_stream() {
+        let response = mock_response(concat!(
+            r#"data: {"id":"t1","object":"text_completion","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[{"index":0,"text":"fn ","finish_reason":null}]}"#,
+            "\n\n",
+        ));
+
+        let err =
+            JsonStream::<ChatCompletionStream<crate::response::TextChoiceStream>>::new(response)
+                .collect_completion()
+                .unwrap_err();
+        assert!(matches!(err, Error::Stream(StreamError::Incomplete(_))));
+    }
+
+    #[test]
+    fn test_usage_after_stream() {
+        let response = mock_response(concat!(
+            r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":"stop"}],"usage":null}"#,
+            "\n\n",
+            r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"deepseek-chat","system_fingerprint":"fp","choices":[],"usage":{"completion_tokens":1,"prompt_tokens":4,"prompt_cache_hit_tokens":0,"prompt_cache_miss_tokens":4,"total_tokens":5}}"#,
+            "\n\n",
+            "data: [DONE]\n\n",
+        ));
+
+        let mut stream =
+            JsonStream::<ChatCompletionStream<crate::response::JSONChoiceStream>>::new(response);
+        let first = stream.next().unwrap().unwrap();
+        assert!(first.usage.is_none());
+        assert!(stream.usage().is_none());
+
+        let last = stream.next().unwrap().unwrap();
+        assert!(last.choices.is_empty());
+        assert!(stream.next().is_none());
+        assert_eq!(stream.usage().unwrap().total_tokens, 5);
+    }
 }
@@ -1,3 +1,11 @@
+pub mod batch;
+pub(crate) mod cassette;
 pub mod client;
+pub mod conversation;
 mod error;
+#[cfg(test)]
+pub(crate) mod fake;
 pub mod json_stream;
+mod structured;
+pub mod tools;
+pub mod transport;
@@ -0,0 +1,73 @@
+use crate::TransportError;
+use std::io::Read;
+
+/// The body of a response, read incrementally.
+pub type Body = Box<dyn Read + Send>;
+
+/// Sends HTTP requests on behalf of the blocking `DeepSeekClient`.
+///
+/// The client builds a complete `http::Request` (URL, headers and JSON body) and hands it to
+/// the transport, which returns the status, headers and a reader over the body. Implement
+/// this trait to run the client on another HTTP stack or against an in-memory fake.
+///
+/// # Examples
+///
+/// ```ignore
+/// struct MyTransport;
+///
+/// impl Transport for MyTransport {
+///     fn send(&self, request: http::Request<Vec<u8>>) -> Result<http::Response<Body>, TransportError> {
+///         todo!()
+///     }
+/// }
+///
+/// let client = DeepSeekClientBuilder::new("your_api_key".to_string())
+///     .with_blocking_transport(MyTransport)
+///     .build_blocking()?;
+/// ```
+pub trait Transport: Send + Sync + 'static {
+    /// Sends the request and returns the response once its headers have arrived.
+    fn send(&self, request: http::Request<Vec<u8>>)
+        -> Result<http::Response<Body>, TransportError>;
+}
+
+/// The default `Transport`, backed by a `reqwest::blocking::Client`.
+#[derive(Clone)]
+pub struct ReqwestTransport {
+    client: reqwest::blocking::Client,
+}
+
+impl ReqwestTransport {
+    /// Creates a new `ReqwestTransport` sending requests with `client`.
+    pub fn new(client: reqwest::blocking::Client) -> Self {
+        ReqwestTransport { client }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(
+        &self,
+        request: http::Request<Vec<u8>>,
+    ) -> Result<http::Response<Body>, TransportError> {
+        let request = reqwest::blocking::Request::try_from(request)?;
+        let resp = self.client.execute(request)?;
+
+        let mut builder = http::Response::builder()
+            .status(resp.status())
+            .version(resp.version());
+        if let Some(headers) = builder.headers_mut() {
+            *headers = resp.headers().clone();
+        }
+        let body: Body = Box::new(resp);
+        Ok(builder
+            .body(body)
+            .expect("status and headers come from a valid response"))
+    }
+}
+
+/// Reads the whole body into memory.
+pub(crate) fn read_body(mut body: Body) -> Result<Vec<u8>, crate::Error> {
+    let mut buf = Vec::new();
+    body.read_to_end(&mut buf)?;
+    Ok(buf)
+}
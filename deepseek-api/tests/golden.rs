@@ -0,0 +1,266 @@
+//! Snapshots of the JSON bodies sent for `CompletionsRequest` and `FMICompletionsRequest`.
+//!
+//! Each case is compared with `tests/golden/<name>.json`. After an intended change of the
+//! wire format, regenerate the files with `UPDATE_GOLDEN=1 cargo test --test golden` and
+//! review the diff.
+
+use deepseek_api::request::{
+    MessageRequest, ResponseType, Stop, StreamOptions, ToolChoice, ToolMessageRequest, ToolObject,
+};
+use deepseek_api::response::{AssistantMessage, ModelType};
+use deepseek_api::{CompletionsRequestBuilder, FMICompletionsRequestBuilder, RequestBuilder};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::{env, fs};
+
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct GetWeather {
+    /// The location to get the weather for.
+    location: String,
+}
+
+fn messages() -> Vec<MessageRequest> {
+    let mut assistant = AssistantMessage::new("");
+    assistant.reasoning_content = Some("The user wants the weather.".to_string());
+    assistant.tool_calls = Some(vec![serde_json::from_value(serde_json::json!({
+        "id": "call_0",
+        "type": "function",
+        "function": {"name": "get_weather", "arguments": "{\"location\":\"Paris\"}"}
+    }))
+    .unwrap()]);
+
+    vec![
+        MessageRequest::sys("You are a helpful assistant."),
+        MessageRequest::user("What is the weather in Paris?"),
+        MessageRequest::Assistant(assistant),
+        MessageRequest::Tool(ToolMessageRequest::new("Sunny, 24°C", "call_0")),
+    ]
+}
+
+fn tools() -> Vec<ToolObject> {
+    vec![ToolObject::from_type::<GetWeather>(
+        "get_weather",
+        "Get the weather of a location.",
+    )]
+}
+
+/// Decodes the body as sent by the clients; `serde_json::to_value` would widen the `f32`
+/// parameters, e.g. `0.2` to `0.20000000298023224`.
+fn wire<T: Serialize>(request: T) -> Value {
+    serde_json::from_slice(&serde_json::to_vec(&request).unwrap()).unwrap()
+}
+
+fn chat(builder: CompletionsRequestBuilder<'_>) -> Value {
+    wire(builder.build())
+}
+
+fn fim(builder: FMICompletionsRequestBuilder) -> Value {
+    wire(builder.build())
+}
+
+fn chat_cases(messages: &[MessageRequest], tools: &[ToolObject]) -> Vec<(&'static str, Value)> {
+    let new = || CompletionsRequestBuilder::new(messages);
+    let all = || {
+        new()
+            .max_tokens(1024)
+            .unwrap()
+            .stream(true)
+            .stream_options(StreamOptions::new(true))
+            .response_format(ResponseType::Json)
+            .stop(Stop::Multiple(vec!["END".to_string(), "\n\n".to_string()]))
+            .tools(tools)
+            .tool_choice(ToolChoice::auto())
+            .temperature(0.7)
+            .unwrap()
+            .top_p(0.9)
+            .unwrap()
+            .presence_penalty(0.5)
+            .unwrap()
+            .frequency_penalty(-0.5)
+            .unwrap()
+            .logprobs(true)
+            .top_logprobs(5)
+            .unwrap()
+            .n(2)
+            .unwrap()
+            .seed(42)
+            .user("user-1")
+            .logit_bias(BTreeMap::from([(100, -100), (200, 50)]))
+            .unwrap()
+    };
+
+    vec![
+        ("chat_defaults", chat(new())),
+        ("chat_max_tokens", chat(new().max_tokens(1024).unwrap())),
+        ("chat_stream", chat(new().stream(true))),
+        (
+            "chat_stream_options",
+            chat(new().stream(true).stream_options(StreamOptions::new(true))),
+        ),
+        (
+            "chat_response_format_text",
+            chat(new().response_format(ResponseType::Text)),
+        ),
+        (
+            "chat_response_format_json",
+            chat(new().response_format(ResponseType::Json)),
+        ),
+        (
+            "chat_stop_single",
+            chat(new().stop(Stop::Single("END".to_string()))),
+        ),
+        (
+            "chat_stop_multiple",
+            chat(new().stop(Stop::Multiple(vec!["END".to_string(), "\n\n".to_string()]))),
+        ),
+        ("chat_tools", chat(new().tools(tools))),
+        (
+            "chat_tool_choice_none",
+            chat(new().tools(tools).tool_choice(ToolChoice::none())),
+        ),
+        (
+            "chat_tool_choice_auto",
+            chat(new().tools(tools).tool_choice(ToolChoice::auto())),
+        ),
+        (
+            "chat_tool_choice_required",
+            chat(new().tools(tools).tool_choice(ToolChoice::required())),
+        ),
+        (
+            "chat_tool_choice_function",
+            chat(
+                new()
+                    .tools(tools)
+                    .tool_choice(ToolChoice::function("get_weather")),
+            ),
+        ),
+        ("chat_temperature", chat(new().temperature(0.7).unwrap())),
+        ("chat_top_p", chat(new().top_p(0.9).unwrap())),
+        (
+            "chat_penalties",
+            chat(
+                new()
+                    .presence_penalty(0.5)
+                    .unwrap()
+                    .frequency_penalty(-0.5)
+                    .unwrap(),
+            ),
+        ),
+        (
+            "chat_logprobs",
+            chat(new().logprobs(true).top_logprobs(5).unwrap()),
+        ),
+        ("chat_n", chat(new().n(2).unwrap())),
+        ("chat_seed", chat(new().seed(42))),
+        ("chat_user", chat(new().user("user-1"))),
+        (
+            "chat_logit_bias",
+            chat(
+                new()
+                    .logit_bias(BTreeMap::from([(100, -100), (200, 50)]))
+                    .unwrap(),
+            ),
+        ),
+        (
+            "chat_keep_reasoning_content",
+            chat(new().keep_reasoning_content(true)),
+        ),
+        ("chat_all_parameters", chat(all())),
+        (
+            "reasoner_all_parameters",
+            chat(all().use_model(ModelType::DeepSeekReasoner)),
+        ),
+        (
+            "custom_model",
+            chat(new().use_model("my-fine-tune").temperature(0.7).unwrap()),
+        ),
+    ]
+}
+
+fn fim_cases() -> Vec<(&'static str, Value)> {
+    let new = || FMICompletionsRequestBuilder::new("fn add(a: i32, b: i32) -> i32 {", "}");
+
+    vec![
+        ("fim_defaults", fim(new())),
+        ("fim_echo", fim(new().echo(true))),
+        ("fim_max_tokens", fim(new().max_tokens(128).unwrap())),
+        (
+            "fim_stop_single",
+            fim(new().stop(Stop::Single("\n".to_string()))),
+        ),
+        (
+            "fim_stop_multiple",
+            fim(new().stop(Stop::Multiple(vec!["\n".to_string(), "}".to_string()]))),
+        ),
+        (
+            "fim_stream_options",
+            fim(new().stream(true).stream_options(StreamOptions::new(true))),
+        ),
+        (
+            "fim_all_parameters",
+            fim(new()
+                .echo(true)
+                .frequency_penalty(0.5)
+                .unwrap()
+                .logprobs(true)
+                .max_tokens(128)
+                .unwrap()
+                .presence_penalty(-0.5)
+                .unwrap()
+                .stop(Stop::Single("\n".to_string()))
+                .stream(true)
+                .stream_options(StreamOptions::new(true))
+                .temperature(0.2)
+                .unwrap()
+                .top_p(0.95)
+                .unwrap()),
+        ),
+    ]
+}
+
+fn check(cases: Vec<(&'static str, Value)>) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut mismatches = Vec::new();
+    for (name, value) in cases {
+        let path = dir.join(format!("{}.json", name));
+        let actual = serde_json::to_string_pretty(&value).unwrap() + "\n";
+        if update {
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(&path, &actual).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => mismatches.push(format!(
+                "{} differs:\n--- expected\n{}+++ actual\n{}",
+                name, expected, actual
+            )),
+            Err(err) => mismatches.push(format!("{}: cannot read {:?}: {}", name, path, err)),
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{}\n\nRun `UPDATE_GOLDEN=1 cargo test --test golden` if the change is intended.",
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn test_chat_completions_golden() {
+    let messages = messages();
+    let tools = tools();
+    check(chat_cases(&messages, &tools));
+}
+
+#[test]
+fn test_fim_completions_golden() {
+    check(fim_cases());
+}
@@ -0,0 +1,33 @@
+#![cfg(feature = "derive")]
+
+use deepseek_api::request::{Tool, ToolObject};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Get the weather of a location.
+#[derive(Deserialize, JsonSchema, Tool)]
+#[allow(dead_code)]
+struct GetWeather {
+    /// The location to get the weather for.
+    location: String,
+}
+
+#[derive(Deserialize, JsonSchema, Tool)]
+#[tool(name = "now", description = "Get the current time")]
+struct CurrentTime {}
+
+#[test]
+fn test_derive_defaults_to_name_and_doc_comment() {
+    assert_eq!(GetWeather::NAME, "get_weather");
+    assert_eq!(GetWeather::DESCRIPTION, "Get the weather of a location.");
+    assert_eq!(
+        GetWeather::tool_object(),
+        ToolObject::from_type::<GetWeather>("get_weather", "Get the weather of a location.")
+    );
+}
+
+#[test]
+fn test_derive_with_attributes() {
+    assert_eq!(CurrentTime::NAME, "now");
+    assert_eq!(CurrentTime::DESCRIPTION, "Get the current time");
+}
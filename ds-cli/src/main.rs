@@ -50,7 +50,7 @@ fn main() -> Result<()> {
     let req_state = Arc::new(RwLock::new(ShareState::default()));
 
     {
-        let client = DeepSeekClientBuilder::new(args.api_key.clone()).build()?;
+        let client = DeepSeekClientBuilder::new(args.api_key.clone()).build_blocking()?;
         let req_state = req_state.clone();
         thread::spawn(move || loop {
             //request thread
@@ -12,7 +12,7 @@ struct Args {
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    let client = DeepSeekClientBuilder::new(args.api_key.clone()).build()?;
+    let client = DeepSeekClientBuilder::new(args.api_key.clone()).build_blocking()?;
 
     let balances = client.balance()?;
     println!("balances {:?}", balances);
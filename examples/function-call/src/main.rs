@@ -1,10 +1,11 @@
 use anyhow::Result;
 use clap::Parser;
 use deepseek_api::request::MessageRequest;
-use deepseek_api::request::{Function, ToolMessageRequest, ToolObject, ToolType};
+use deepseek_api::request::{Tool, ToolMessageRequest, ToolObject};
 use deepseek_api::response::FinishReason;
 use deepseek_api::{CompletionsRequestBuilder, DeepSeekClientBuilder, RequestBuilder};
-use schemars::schema::SchemaObject;
+use schemars::JsonSchema;
+use serde::Deserialize;
 use std::vec;
 
 #[derive(Parser, Debug)]
@@ -14,44 +15,34 @@ struct Args {
     pub api_key: String,
 }
 
+/// Get weather of an location, the user shoud supply a location first
+#[derive(Debug, Deserialize, JsonSchema, Tool)]
+#[allow(dead_code)]
+struct GetWeather {
+    /// The location to get the weather for
+    location: String,
+    /// The unit of temperature
+    unit: Option<Unit>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Unit {
+    Celsius,
+    Fahrenheit,
+}
+
 /// This example demonstrates how to use function calling in the DeepSeek API.
 /// It defines a function to get the weather of a location, and then calls that function
-/// based on the user's input. The function is defined using JSON Schema, and the API
-/// will automatically parse the user's input to match the function's parameters.
+/// based on the user's input. The JSON Schema of the function parameters is derived from
+/// the `GetWeather` struct, and the arguments chosen by the model are parsed back into it.
 /// More detail can refer to https://api-docs.deepseek.com/guides/function_calling
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
     let client = DeepSeekClientBuilder::new(args.api_key.clone()).build()?;
-    let parameters: SchemaObject = serde_json::from_str(
-        r#"{
-        "type": "object",
-        "properties": {
-            "location": {
-                "type": "string",
-                "description": "The location to get the weather for"
-            },
-            "unit": {
-                "type": "string",
-                "enum": ["celsius", "fahrenheit"],
-                "description": "The unit of temperature"
-            }
-        },
-        "required": ["location"]
-    }"#,
-    )?;
-
-    let tool_object = ToolObject {
-        tool_type: ToolType::Function,
-        function: Function {
-            name: "get_weather".to_string(),
-            description: "Get weather of an location, the user shoud supply a location first"
-                .to_string(),
-            parameters,
-        },
-    };
 
-    let tool_objects: Vec<ToolObject> = vec![tool_object];
+    let tool_objects: Vec<ToolObject> = vec![GetWeather::tool_object()];
     let mut messages = vec![MessageRequest::user("How's the weather in Hangzhou?")];
     let resp = CompletionsRequestBuilder::new(&messages)
         .tools(&tool_objects)
@@ -65,7 +56,8 @@ async fn main() -> Result<()> {
                 id = tool[0].id.clone();
                 println!("Function id: {}", id);
                 println!("Function name: {}", tool[0].function.name);
-                println!("Function parameters: {:?}", tool[0].function.arguments);
+                let arguments: GetWeather = tool[0].parse_arguments()?;
+                println!("Function parameters: {:?}", arguments);
             }
             messages.push(MessageRequest::Assistant(msg.clone()));
         }
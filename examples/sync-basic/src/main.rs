@@ -16,7 +16,7 @@ fn main() -> Result<()> {
 
     let client = DeepSeekClientBuilder::new(args.api_key.clone())
         .with_timeout(300)
-        .build()?;
+        .build_blocking()?;
 
     let mut history = vec![];
     let resp = CompletionsRequestBuilder::new(&[MessageRequest::user("hello world")])